@@ -0,0 +1,51 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use warp::http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE, ETAG};
+use warp::http::StatusCode;
+use warp::hyper::Body;
+use warp::reply::Response;
+
+// Answers with the value as JSON, or with an empty 304 if the client's If-None-Match has its ETag
+pub fn cached_json<T: Serialize>(value: &T, if_none_match: Option<&str>, max_age: u64) -> Response {
+    let body = match serde_json::to_vec(value) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Failed to serialize response: {}", e);
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return response
+        },
+    };
+    let etag = etag(&body);
+
+    let mut response = if if_none_match.map_or(false, |tags| matches(tags, &etag)) {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        response
+    } else {
+        let mut response = Response::new(Body::from(body));
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    };
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        headers.insert(ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("public, max-age={}", max_age)) {
+        headers.insert(CACHE_CONTROL, value);
+    }
+    response
+}
+
+// Strong ETag of the body, so the database doesn't have to track versions
+fn etag(body: &[u8]) -> String {
+    let hash: String = Sha256::digest(body)[..16].iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("\"{}\"", hash)
+}
+
+// If-None-Match holds `*` or a list of tags, weak ones compare equal to strong ones
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
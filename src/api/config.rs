@@ -1,17 +1,215 @@
-use std::net::SocketAddr;
+use std::collections::HashMap;
+use std::net::{AddrParseError, SocketAddr};
+use std::time::SystemTime;
 use serde::Deserialize;
 
+use super::policy::AccessPolicy;
+use super::ratelimit::{self, RateLimit};
+use crate::config::Issues;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct APIConfig {
     pub address: String,
     pub port: u16,
+    #[serde(default)]
+    pub policies: Vec<AccessPolicy>,
+    // Seconds after which a game server without heartbeat is considered offline
+    #[serde(default = "default_heartbeat_expiry")]
+    pub heartbeat_expiry: u64,
+    // Cutup scores above this are rejected
+    #[serde(default = "default_max_score")]
+    pub max_score: i64,
+    // Limits per route name, requests are counted per API key or client IP
+    #[serde(default)]
+    pub rate_limits: HashMap<String, RateLimit>,
+    // Largest accepted JSON body in bytes
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: u64,
+    // Largest accepted body of /v1/ingest in bytes
+    #[serde(default = "default_max_ingest_body_size")]
+    pub max_ingest_body_size: u64,
+    // Most events accepted in one /v1/ingest batch
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    // Seconds for which event ids of submissions are remembered
+    #[serde(default = "default_dedup_ttl")]
+    pub dedup_ttl: u64,
+    // API keys allowed to subscribe to /v1/events, the feed is disabled without any
+    #[serde(default)]
+    pub feed_keys: Vec<String>,
+    // Refuses writes without the API key of a registered game server. Unset, they are refused once a server is
+    // registered, false keeps accepting them as anonymous writes.
+    #[serde(default)]
+    pub require_server_key: Option<bool>,
+    // Serves HTTPS instead of HTTP if set
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub public: PublicConfig,
+}
+
+// The unauthenticated /v1/public routes used by the website
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublicConfig {
+    // Entries per page if the client doesn't ask for a size, and the most it may ask for
+    #[serde(default = "default_page_size")]
+    pub page_size: u32,
+    #[serde(default = "default_max_page_size")]
+    pub max_page_size: u32,
+    // Seconds clients and proxies may cache responses
+    #[serde(default = "default_cache_max_age")]
+    pub cache_max_age: u64,
+    #[serde(default)]
+    pub seasons: Vec<Season>,
+}
+
+impl Default for PublicConfig {
+    fn default() -> PublicConfig {
+        PublicConfig {
+            page_size: default_page_size(),
+            max_page_size: default_max_page_size(),
+            cache_max_age: default_cache_max_age(),
+            seasons: Vec::new(),
+        }
+    }
+}
+
+// Season leaderboards rank the best scores set from `start` until `end`, e.g. "2024-01-01T00:00:00Z"
+#[derive(Debug, Clone, Deserialize)]
+pub struct Season {
+    pub name: String,
+    pub start: String,
+    pub end: String,
+}
+
+impl Season {
+    pub fn period(&self) -> Result<(SystemTime, SystemTime), humantime::TimestampError> {
+        Ok((humantime::parse_rfc3339_weak(&self.start)?, humantime::parse_rfc3339_weak(&self.end)?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TlsConfig {
+    // PEM certificate chain and private key
+    pub cert_path: String,
+    pub key_path: String,
+    // PEM CA certificates, game servers have to present a client certificate signed by one of them if set
+    pub client_ca_path: Option<String>,
+    // Seconds between checks for renewed certificate files, 0 to disable
+    #[serde(default = "default_tls_reload_interval")]
+    pub reload_interval: u64,
+}
+
+fn default_heartbeat_expiry() -> u64 {
+    90
+}
+
+fn default_max_score() -> i64 {
+    9999999
+}
+
+fn default_max_body_size() -> u64 {
+    16 * 1024
+}
+
+fn default_max_ingest_body_size() -> u64 {
+    1024 * 1024
+}
+
+fn default_max_batch_size() -> usize {
+    500
+}
+
+fn default_dedup_ttl() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_page_size() -> u32 {
+    25
+}
+
+fn default_max_page_size() -> u32 {
+    100
+}
+
+fn default_cache_max_age() -> u64 {
+    60
+}
+
+fn default_tls_reload_interval() -> u64 {
+    300
 }
 
 impl APIConfig {
-    pub fn address(&self) -> SocketAddr {
+    pub fn address(&self) -> Result<SocketAddr, AddrParseError> {
         // Setup SocketAddr using address and port
-        format!("{}:{}", self.address, self.port)
-            .parse()
-            .expect("Invalid address")
+        format!("{}:{}", self.address, self.port).parse()
+    }
+
+    pub fn policy(&self, name: &str) -> Option<&AccessPolicy> {
+        self.policies.iter().find(|policy| policy.name == name)
+    }
+
+    pub fn season(&self, name: &str) -> Option<&Season> {
+        self.public.seasons.iter().find(|season| season.name == name)
+    }
+
+    // Routes without their own limit use the "default" one, no limit if neither is set
+    pub fn rate_limit(&self, route: &str) -> Option<&RateLimit> {
+        self.rate_limits.get(route).or_else(|| self.rate_limits.get("default"))
+    }
+
+    pub fn validate(&self, issues: &mut Issues) {
+        if let Err(e) = self.address() {
+            issues.push("api.address", format!("\"{}\" with port {} is not a valid socket address: {}", self.address, self.port, e));
+        }
+        issues.check(self.port != 0, "api.port", "must not be 0");
+        issues.check(self.max_score > 0, "api.max_score", "must be positive");
+        issues.check(self.heartbeat_expiry > 0, "api.heartbeat_expiry", "must be at least 1 second");
+        issues.check(self.max_body_size > 0, "api.max_body_size", "must be at least 1 byte");
+        issues.check(self.max_ingest_body_size > 0, "api.max_ingest_body_size", "must be at least 1 byte");
+        issues.check(self.max_batch_size > 0, "api.max_batch_size", "must be at least 1");
+        issues.check(self.dedup_ttl > 0, "api.dedup_ttl", "must be at least 1 second");
+        issues.check(self.feed_keys.iter().all(|key| !key.is_empty()), "api.feed_keys", "must not contain empty keys");
+        if let Some(tls) = &self.tls {
+            if let Err(e) = super::tls::server_config(tls) {
+                issues.push("api.tls", e);
+            }
+        }
+        issues.check(self.public.page_size > 0, "api.public.page_size", "must be at least 1");
+        issues.check(
+            self.public.max_page_size >= self.public.page_size,
+            "api.public.max_page_size",
+            "must not be smaller than api.public.page_size",
+        );
+        for (i, season) in self.public.seasons.iter().enumerate() {
+            let path = format!("api.public.seasons[{}]", i);
+            issues.check(!season.name.is_empty(), format!("{}.name", path), "must not be empty");
+            if self.public.seasons[..i].iter().any(|other| other.name == season.name) {
+                issues.push(format!("{}.name", path), format!("season \"{}\" is defined more than once", season.name));
+            }
+            match season.period() {
+                Ok((start, end)) => issues.check(start < end, format!("{}.end", path), "must be after the start"),
+                Err(e) => issues.push(path, format!("start and end must be RFC 3339 timestamps: {}", e)),
+            }
+        }
+        for (route, limit) in &self.rate_limits {
+            let path = format!("api.rate_limits.{}", route);
+            issues.check(
+                ratelimit::ROUTES.contains(&route.as_str()),
+                path.clone(),
+                format!("unknown route, use one of {}", ratelimit::ROUTES.join(", ")),
+            );
+            issues.check(limit.burst > 0, format!("{}.burst", path), "must be at least 1");
+        }
+        for (i, policy) in self.policies.iter().enumerate() {
+            let path = format!("api.policies[{}]", i);
+            issues.check(!policy.name.is_empty(), format!("{}.name", path), "must not be empty");
+            if self.policies[..i].iter().any(|other| other.name == policy.name) {
+                issues.push(format!("{}.name", path), format!("policy \"{}\" is defined more than once", policy.name));
+            }
+            for role in policy.any_of.iter().chain(policy.all_of.iter()).chain(policy.deny.iter()) {
+                issues.check_id(*role, format!("{}.roles", path));
+            }
+        }
     }
 }
\ No newline at end of file
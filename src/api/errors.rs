@@ -3,9 +3,11 @@ use warp::reject::Reject;
 use warp::Reply;
 use warp::Rejection;
 use warp::http::StatusCode;
+use warp::http::header::{HeaderValue, RETRY_AFTER};
 use poise::serenity_prelude::Error as SerenityError;
 use tokio_postgres::Error as PostgresError;
 use crate::api::models;
+use crate::api::ratelimit::RateLimited;
 
 #[derive(Debug)]
 pub struct DiscordError(SerenityError);
@@ -29,15 +31,27 @@ impl From<PostgresError> for DatabaseError {
 
 impl Reject for DatabaseError {}
 
-async fn construct_response(code: StatusCode, message: String) -> (models::DefaultResponse, StatusCode) {
-    (models::DefaultResponse {
+// Writes refused because of the game server registry
+#[derive(Debug)]
+pub enum ServerKeyRejected {
+    // No key of a registered server while `api.require_server_key` is set, or unset and a server is registered
+    Unknown,
+    Disabled,
+}
+
+impl Reject for ServerKeyRejected {}
+
+async fn construct_response(code: StatusCode, message: String) -> (models::ErrorResponse, StatusCode) {
+    (models::ErrorResponse {
         status: code.as_str().to_string(),
         message: message,
+        request_id: crate::logging::field("request_id"),
     }, code)
 }
 
 pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
     // dbg!("Handling rejection: {:?}", &err);
+    let mut retry_after = None;
     let mut response = construct_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error".to_string()).await;
 
     if err.is_not_found() {
@@ -54,6 +68,26 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
     } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
         log::debug!("Bad request: {:?}", e);
         response = construct_response(StatusCode::BAD_REQUEST, "Bad request".to_string()).await;
+    } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        log::debug!("Invalid query: {:?}", e);
+        response = construct_response(StatusCode::BAD_REQUEST, "Bad request".to_string()).await;
+    } else if let Some(e) = err.find::<ServerKeyRejected>() {
+        response = match e {
+            ServerKeyRejected::Unknown => construct_response(StatusCode::UNAUTHORIZED, "API key of a registered game server required".to_string()).await,
+            ServerKeyRejected::Disabled => construct_response(StatusCode::FORBIDDEN, "Game server is disabled".to_string()).await,
+        };
+    } else if let Some(e) = err.find::<warp::cors::CorsForbidden>() {
+        log::debug!("CORS request refused: {:?}", e);
+        response = construct_response(StatusCode::FORBIDDEN, "CORS request not allowed".to_string()).await;
+    } else if let Some(e) = err.find::<RateLimited>() {
+        retry_after = Some(e.retry_after);
+        response = construct_response(StatusCode::TOO_MANY_REQUESTS, "Too many requests".to_string()).await;
+    } else if let Some(e) = err.find::<warp::reject::PayloadTooLarge>() {
+        log::debug!("Payload too large: {:?}", e);
+        response = construct_response(StatusCode::PAYLOAD_TOO_LARGE, "Payload too large".to_string()).await;
+    } else if let Some(e) = err.find::<warp::reject::LengthRequired>() {
+        log::debug!("Length required: {:?}", e);
+        response = construct_response(StatusCode::LENGTH_REQUIRED, "Content-Length required".to_string()).await;
     } else if let Some(e) = err.find::<warp::reject::UnsupportedMediaType>() {
         log::debug!("Unsupported media type: {:?}", e);
         response = construct_response(StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported media type".to_string()).await;
@@ -61,8 +95,14 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
         log::error!("Internal server error encountered: {:?}", err);
     }
 
-    Ok(warp::reply::with_status(
+    let mut reply = warp::reply::with_status(
         warp::reply::json(&response.0),
         response.1,
-    ))
+    ).into_response();
+    if let Some(retry_after) = retry_after {
+        // Rounded up, a client retrying after 0 seconds would just be limited again
+        let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        reply.headers_mut().insert(RETRY_AFTER, HeaderValue::from(secs));
+    }
+    Ok(reply)
 }
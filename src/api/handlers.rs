@@ -1,44 +1,111 @@
 use super::models::AppState;
 use super::models;
+use super::policy::AccessPolicy;
+use super::config::APIConfig;
+use super::openapi;
+use super::caching;
+use crate::status::ServerStatus;
+use crate::discord::notifications;
+use crate::database::ingest::{IngestEvent, IngestResult};
+use crate::database::rankings::RankingFilter;
+use crate::database::servers::GameServer;
+use crate::bus::{self, BotEvent};
+use crate::metrics;
+use crate::logging;
 use poise::serenity_prelude as serenity;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use futures_util::{future, stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use warp::Reply;
+
+fn access_response(config: &APIConfig, status: &str, message: &str, policy: Option<&str>, rule: Option<String>) -> warp::reply::Response {
+    let result = match status {
+        "OK" => "allowed",
+        "UNAUTHORIZED" => "denied",
+        _ => "error",
+    };
+    // Only configured names become labels, requested names are arbitrary and would blow up the metrics
+    let label = match policy {
+        None => "none",
+        Some("legacy") => "legacy",
+        Some(name) if config.policy(name).is_some() => name,
+        Some(_) => "unknown",
+    };
+    metrics::WHITELIST_CHECKS.with_label_values(&[label, result]).inc();
+
+    warp::reply::json(&models::CheckSteamidResponse {
+        status: status.to_string(),
+        message: message.to_string(),
+        policy: policy.map(|name| name.to_string()),
+        rule,
+    }).into_response()
+}
+
+// A lookup the decision depends on failed, the driver isn't let in and the plugin can retry
+fn unavailable_response(config: &APIConfig, message: &str, policy: &str) -> warp::reply::Response {
+    let mut response = access_response(config, "ERROR", message, Some(policy), None);
+    *response.status_mut() = warp::http::StatusCode::SERVICE_UNAVAILABLE;
+    response
+}
 
 pub async fn check_steamid(data: models::CheckSteamid, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("Checking steamid: {:?}", &data);
 
-    match state.database.query_one(
+    let config = state.config.get();
+    let policy = match (&data.policy, &data.roles) {
+        (Some(name), _) => match config.api.policy(name) {
+            Some(policy) => policy.clone(),
+            None => {
+                log::warn!("Unknown access policy requested: {}", name);
+                return Ok(access_response(&config.api, "ERROR", "Unknown policy", Some(name), None))
+            }
+        },
+        (None, Some(roles)) => AccessPolicy::from_roles(roles),
+        (None, None) => return Ok(access_response(&config.api, "ERROR", "No policy or roles given", None, None)),
+    };
+
+    if policy.is_banned(data.steamid) {
+        return Ok(access_response(&config.api, "UNAUTHORIZED", "Not authorized", Some(&policy.name), Some("banned".to_string())))
+    }
+
+    match state.database.active_ban(data.steamid, "servers").await {
+        Ok(Some(ban)) => {
+            log::info!("Steamid {} is banned: {:?}", data.steamid, ban);
+            return Ok(access_response(&config.api, "UNAUTHORIZED", "Banned", Some(&policy.name), Some(format!("ban:{}", ban.id))))
+        },
+        Ok(None) => {},
+        Err(e) => {
+            log::error!("Failed to fetch bans: {}", e);
+            return Ok(unavailable_response(&config.api, "Bans could not be checked", &policy.name))
+        },
+    }
+
+    match state.database.query(
         "SELECT discordid FROM steamids WHERE steamid = $1",
         &[&data.steamid],
-    ).await {
-        Ok(row) => {
+    ).await.map(|rows| rows.into_iter().next()) {
+        Ok(None) => Ok(access_response(&config.api, "UNAUTHORIZED", "Not authorized", Some(&policy.name), Some("unlinked".to_string()))),
+        Ok(Some(row)) => {
             let discordid: i64 = row.get(0);
-            let user_roles: Vec<serenity::model::prelude::RoleId> = match state.http.get_member(state.config.discord.guild.into(), serenity::UserId::from(discordid as u64)).await {
-                Ok(member) => member.roles.to_vec(),
+            let user_roles: Vec<u64> = match state.http.get_member(config.discord.guild.into(), serenity::UserId::from(discordid as u64)).await {
+                Ok(member) => member.roles.iter().map(|role| role.get()).collect(),
                 Err(e) => {
                     log::error!("Failed to fetch member roles: {}", e);
-                    return Ok(warp::reply::json(&models::DefaultResponse {
-                        status: "UNAUTHORIZED".to_string(),
-                        message: "Not authorized".to_string(),
-                    }))
+                    metrics::discord_error("whitelist");
+                    return Ok(access_response(&config.api, "UNAUTHORIZED", "Not authorized", Some(&policy.name), Some("not_member".to_string())))
                 }
             };
-            if data.roles.iter().any(|role| user_roles.contains(&poise::serenity_prelude::model::id::RoleId::from(*role as u64))) {
-                return Ok(warp::reply::json(&models::DefaultResponse {
-                    status: "OK".to_string(),
-                    message: "Authorized".to_string(),
-                }))
+            let decision = policy.evaluate(&user_roles);
+            if decision.allowed {
+                Ok(access_response(&config.api, "OK", "Authorized", Some(&policy.name), Some(decision.rule)))
             } else {
-                return Ok(warp::reply::json(&models::DefaultResponse {
-                    status: "UNAUTHORIZED".to_string(),
-                    message: "Not authorized".to_string(),
-                }))
+                Ok(access_response(&config.api, "UNAUTHORIZED", "Not authorized", Some(&policy.name), Some(decision.rule)))
             }
         },
         Err(e) => {
             log::error!("Failed to fetch discordid: {}", e);
-            return Ok(warp::reply::json(&models::DefaultResponse {
-                status: "UNAUTHORIZED".to_string(),
-                message: "Not authorized".to_string(),
-            }))
+            Ok(unavailable_response(&config.api, "Link could not be checked", &policy.name))
         }
     }
 }
@@ -65,71 +132,397 @@ pub async fn fetch_cutup_score(data: models::ScoreRequest, state: AppState) -> R
     }
 }
 
-pub async fn insert_cutup_score(data: models::InsertScoreRequest, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
-    log::info!("Cutup highscore insert: {:?}", &data);
+// Answer of the single event routes. Results stored by a batch or by older versions are mapped to the same shape,
+// so a retry gets the answer of the first submission wherever it was sent.
+fn event_response(result: IngestResult, applied: &str) -> models::DefaultResponse {
+    let (status, message) = match result.status.as_str() {
+        "banned" => ("BANNED", result.message.unwrap_or_else(|| "Score ignored".to_string())),
+        "rejected" | "error" => ("ERROR", result.message.unwrap_or_else(|| "Event was refused".to_string())),
+        _ => ("OK", result.message.unwrap_or_else(|| applied.to_string())),
+    };
+    models::DefaultResponse {
+        status: status.to_string(),
+        message,
+        request_id: if status == "ERROR" { logging::field("request_id") } else { None },
+    }
+}
+
+// Name of the game server for log lines, "anonymous" for writes without a registered key
+fn server_name(server: Option<&GameServer>) -> &str {
+    server.map_or("anonymous", |server| server.name.as_str())
+}
 
-    if &data.score > &9999999 {
-        return Ok(warp::reply::json(&models::DefaultResponse {
+// Writes for tracks the server isn't configured for are refused like scores above `max_score`
+fn track_refused(server: Option<&GameServer>, track: &str) -> Option<models::DefaultResponse> {
+    let server = server.filter(|server| !server.allows(track))?;
+    log::warn!(server = server.name.as_str(); "Refused a write for track {} which the server isn't allowed", track);
+    Some(models::DefaultResponse {
+        status: "ERROR".to_string(),
+        message: format!("Track {} is not allowed on this server", track),
+        request_id: logging::field("request_id"),
+    })
+}
+
+pub async fn insert_cutup_score(data: models::InsertScoreRequest, server: Option<GameServer>, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(server = server_name(server.as_ref()); "Cutup highscore insert: {:?}", &data);
+    Ok(warp::reply::json(&apply_cutup_score(data, server.as_ref(), &state).await))
+}
+
+async fn apply_cutup_score(data: models::InsertScoreRequest, server: Option<&GameServer>, state: &AppState) -> models::DefaultResponse {
+    if let Some(refused) = track_refused(server, &data.track) {
+        metrics::SCORE_INSERTS.with_label_values(&["rejected"]).inc();
+        return refused
+    }
+    let config = state.config.get();
+    if data.score > config.api.max_score {
+        metrics::SCORE_INSERTS.with_label_values(&["rejected"]).inc();
+        return models::DefaultResponse {
             status: "ERROR".to_string(),
             message: "Score too high".to_string(),
-        }))
+            request_id: logging::field("request_id"),
+        }
     }
-    
-    match state.database.execute(
-        "INSERT INTO cutup (steamid, track, car, score)
-        VALUES ($1, $2, $3, $4)
-        ON CONFLICT (steamid, track, car)
-        DO UPDATE SET score = EXCLUDED.score
-        WHERE EXCLUDED.score > cutup.score;",
-        &[&data.steamid, &data.track, &data.car, &data.score],
-    ).await {
-        Ok(_) => {
-            return Ok(warp::reply::json(&models::DefaultResponse {
-                status: "OK".to_string(),
-                message: "Inserted".to_string(),
-            }))
+
+    match state.database.active_ban(data.steamid, "scores").await {
+        Ok(Some(ban)) => {
+            log::info!("Ignoring score of banned steamid {}: {:?}", data.steamid, ban);
+            metrics::SCORE_INSERTS.with_label_values(&["banned"]).inc();
+            return models::DefaultResponse {
+                status: "BANNED".to_string(),
+                message: "Score ignored".to_string(),
+                request_id: None,
+            }
+        },
+        Ok(None) => {},
+        Err(e) => log::error!("Failed to fetch bans: {}", e),
+    }
+
+    let event = IngestEvent::CutupScore { steamid: data.steamid, track: data.track, car: data.car, score: data.score, event_id: data.event_id };
+    match state.database.apply_event(&event, config.api.dedup_ttl, server.map(|server| server.id)).await {
+        Ok(applied) => {
+            if applied.duplicate {
+                log::info!("Event {:?} was already processed, returning the stored result", event.event_id());
+            } else {
+                metrics::SCORE_INSERTS.with_label_values(&[applied.result.status.as_str()]).inc();
+            }
+            if let Some(highscore) = applied.highscore {
+                state.bus.publish(BotEvent::from(&highscore));
+                let state = state.clone();
+                // Keeps the request id on the log lines of the announcement
+                tokio::spawn(logging::scope(logging::current(), async move {
+                    state.announcer.announce(&state.http, &state.database, &state.config.get().discord.announcements, &highscore).await;
+                    notifications::notify_record_lost(&state.http, &state.database, &highscore).await;
+                }));
+            }
+            event_response(applied.result, "Inserted")
         },
         Err(e) => {
             log::error!("Failed to insert highscore: {}", e);
-            return Ok(warp::reply::json(&models::DefaultResponse {
+            metrics::SCORE_INSERTS.with_label_values(&["error"]).inc();
+            models::DefaultResponse {
                 status: "ERROR".to_string(),
                 message: "Failed to insert".to_string(),
-            }))
+                request_id: logging::field("request_id"),
+            }
         }
     }
 }
 
-pub async fn update_driver_stats(data: models::UpdateDriverStatsRequest, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
-    log::info!("Driver stats update: {:?}", &data);
-
-    let result = state.database.execute(
-        "INSERT INTO driver_stats (steamid, track, collisions, distance, avgspeed, total_time)
-         VALUES ($1, $2, $3, $4, $5, $6)
-         ON CONFLICT (steamid, track)
-         DO UPDATE SET collisions = driver_stats.collisions + EXCLUDED.collisions,
-                       distance = driver_stats.distance + EXCLUDED.distance,
-                       avgspeed = (driver_stats.avgspeed + EXCLUDED.avgspeed) / 2,
-                       total_time = driver_stats.total_time + EXCLUDED.total_time
-         WHERE driver_stats.steamid = EXCLUDED.steamid AND
-               driver_stats.track = EXCLUDED.track",
-        &[&data.steamid, &data.track, &data.collisions, &data.distance, &data.avgspeed, &data.time],
-    ).await;
-
-    match result {
-        Ok(_) => Ok(warp::reply::json(&models::DefaultResponse {
-            status: "OK".to_string(),
-            message: "Driver stats updated".to_string(),
-        })),
+pub async fn update_driver_stats(data: models::UpdateDriverStatsRequest, server: Option<GameServer>, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(server = server_name(server.as_ref()); "Driver stats update: {:?}", &data);
+    Ok(warp::reply::json(&apply_driver_stats(data, server.as_ref(), &state).await))
+}
+
+async fn apply_driver_stats(data: models::UpdateDriverStatsRequest, server: Option<&GameServer>, state: &AppState) -> models::DefaultResponse {
+    if let Some(refused) = track_refused(server, &data.track) {
+        return refused
+    }
+    let event = IngestEvent::DriverStats {
+        steamid: data.steamid,
+        track: data.track,
+        time: data.time,
+        avgspeed: data.avgspeed,
+        collisions: data.collisions,
+        distance: data.distance,
+        event_id: data.event_id,
+    };
+
+    match state.database.apply_event(&event, state.config.get().api.dedup_ttl, server.map(|server| server.id)).await {
+        Ok(applied) => {
+            if applied.duplicate {
+                log::info!("Event {:?} was already processed, returning the stored result", event.event_id());
+            }
+            event_response(applied.result, "Driver stats updated")
+        },
         Err(e) => {
             log::error!("Failed to update driver stats: {}", e);
-            Ok(warp::reply::json(&models::DefaultResponse {
+            models::DefaultResponse {
                 status: "ERROR".to_string(),
                 message: "Failed to update driver stats".to_string(),
-            }))
+                request_id: logging::field("request_id"),
+            }
         }
     }
 }
 
+// Error in the shape of rejected requests, for handlers which pick the status code themselves
+fn error_reply(code: warp::http::StatusCode, message: String) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&models::ErrorResponse {
+        status: code.as_str().to_string(),
+        message,
+        request_id: logging::field("request_id"),
+    }), code)
+}
+
+pub async fn ingest(server: Option<GameServer>, key: Option<String>, data: models::IngestRequest, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!(server = server_name(server.as_ref()); "Ingesting batch of {} events (idempotency key {:?})", data.events.len(), key);
+    let config = state.config.get();
+
+    if data.events.len() > config.api.max_batch_size {
+        return Ok(error_reply(
+            warp::http::StatusCode::BAD_REQUEST,
+            format!("Too many events, at most {} per batch", config.api.max_batch_size),
+        ))
+    }
+
+    // Scores are checked like on /v1/players/{steamid}/cutup, refused events are answered without touching the database
+    let mut events = Vec::new();
+    let mut rejected = Vec::new();
+    let mut bans = HashMap::new();
+    for (index, event) in data.events.into_iter().enumerate() {
+        if let Some(refused) = track_refused(server.as_ref(), event.track()) {
+            if let IngestEvent::CutupScore { .. } = &event {
+                metrics::SCORE_INSERTS.with_label_values(&["rejected"]).inc();
+            }
+            rejected.push(IngestResult { index, status: "rejected".to_string(), message: Some(refused.message) });
+            continue;
+        }
+        if let IngestEvent::CutupScore { steamid, score, .. } = &event {
+            if *score > config.api.max_score {
+                metrics::SCORE_INSERTS.with_label_values(&["rejected"]).inc();
+                rejected.push(IngestResult { index, status: "rejected".to_string(), message: Some("Score too high".to_string()) });
+                continue;
+            }
+            if !bans.contains_key(steamid) {
+                let banned = match state.database.active_ban(*steamid, "scores").await {
+                    Ok(ban) => ban.is_some(),
+                    Err(e) => {
+                        log::error!("Failed to fetch bans: {}", e);
+                        false
+                    },
+                };
+                bans.insert(*steamid, banned);
+            }
+            if bans[steamid] {
+                metrics::SCORE_INSERTS.with_label_values(&["banned"]).inc();
+                rejected.push(IngestResult { index, status: "banned".to_string(), message: Some("Score ignored".to_string()) });
+                continue;
+            }
+        }
+        events.push((index, event));
+    }
+
+    let cutup_scores: Vec<usize> = events.iter()
+        .filter(|(_, event)| matches!(event, IngestEvent::CutupScore { .. }))
+        .map(|(index, _)| *index)
+        .collect();
+    let ingested = match state.database.ingest(key.as_deref(), events, rejected, config.api.dedup_ttl, server.map(|server| server.id)).await {
+        Ok(ingested) => ingested,
+        Err(e) => {
+            // Nothing was stored, the plugin can retry the whole batch
+            log::error!("Failed to ingest batch: {}", e);
+            return Ok(error_reply(warp::http::StatusCode::INTERNAL_SERVER_ERROR, "Failed to ingest batch".to_string()))
+        },
+    };
+
+    let counted = ingested.results.iter()
+        .filter(|result| cutup_scores.contains(&result.index) && ingested.applied.contains(&result.index));
+    for result in counted {
+        metrics::SCORE_INSERTS.with_label_values(&[result.status.as_str()]).inc();
+    }
+    for highscore in &ingested.highscores {
+        state.bus.publish(BotEvent::from(highscore));
+    }
+    if !ingested.highscores.is_empty() {
+        let state = state.clone();
+        let highscores = ingested.highscores;
+        tokio::spawn(logging::scope(logging::current(), async move {
+            for highscore in highscores {
+                state.announcer.announce(&state.http, &state.database, &state.config.get().discord.announcements, &highscore).await;
+                notifications::notify_record_lost(&state.http, &state.database, &highscore).await;
+            }
+        }));
+    }
+
+    Ok(warp::reply::with_status(warp::reply::json(&models::IngestResponse {
+        status: "OK".to_string(),
+        replayed: ingested.replayed,
+        results: ingested.results,
+    }), warp::http::StatusCode::OK))
+}
+
+pub async fn server_heartbeat(server: Option<GameServer>, data: models::ServerHeartbeat, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
+    log::debug!(server = server_name(server.as_ref()); "Server heartbeat: {:?}", &data);
+
+    let status = ServerStatus {
+        name: data.name,
+        track: data.track,
+        players: data.players,
+        capacity: data.capacity,
+    };
+    state.bus.publish(BotEvent::ServerHeartbeat(status.clone()));
+    // Without a gateway connection (--api-only) the bot runs elsewhere and reads the heartbeats from the database
+    if state.shard_manager.is_none() {
+        if let Err(e) = state.database.save_heartbeat(&status).await {
+            log::error!("Failed to store heartbeat of {}: {}", status.name, e);
+        }
+    }
+    state.servers.update(status);
+
+    Ok(warp::reply::json(&models::DefaultResponse {
+        status: "OK".to_string(),
+        message: "Heartbeat received".to_string(),
+        request_id: None,
+    }))
+}
+
+// Streams bot events as server-sent events until the client disconnects or the bot shuts down
+pub async fn events(query: models::EventsQuery, header_key: Option<String>, state: AppState) -> Result<warp::reply::Response, warp::Rejection> {
+    let config = state.config.get();
+    if config.api.feed_keys.is_empty() {
+        return Ok(error_reply(warp::http::StatusCode::FORBIDDEN, "The event feed is disabled".to_string()).into_response())
+    }
+    // EventSource in browsers can't set headers, so the key may also be given in the query
+    let key = match header_key.or(query.key) {
+        Some(key) if config.api.feed_keys.contains(&key) => key,
+        _ => return Ok(error_reply(warp::http::StatusCode::UNAUTHORIZED, "Missing or unknown API key".to_string()).into_response()),
+    };
+
+    let topics: Vec<&'static str> = match &query.topics {
+        None => bus::TOPICS.to_vec(),
+        Some(topics) => {
+            let mut selected = Vec::new();
+            for topic in topics.split(',').map(|topic| topic.trim()).filter(|topic| !topic.is_empty()) {
+                match bus::TOPICS.iter().find(|known| **known == topic) {
+                    Some(known) => selected.push(*known),
+                    None => return Ok(error_reply(
+                        warp::http::StatusCode::BAD_REQUEST,
+                        format!("Unknown topic \"{}\", use any of {}", topic, bus::TOPICS.join(", ")),
+                    ).into_response()),
+                }
+            }
+            selected
+        },
+    };
+
+    let receiver = state.bus.subscribe();
+    log::info!(
+        api_key = crate::api::key_fingerprint(&key).as_str();
+        "Event feed subscribed to {} ({} subscribers)", topics.join(","), state.bus.subscribers()
+    );
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        match receiver.recv().await {
+            Ok(event) => Some((Ok(event), receiver)),
+            Err(RecvError::Lagged(skipped)) => Some((Err(skipped), receiver)),
+            Err(RecvError::Closed) => None,
+        }
+    });
+    let mut shutdown = state.shutdown.clone();
+    let stream = events
+        .filter_map(move |received| future::ready(match received {
+            Ok(event) if topics.contains(&event.topic()) => Some(warp::sse::Event::default().event(event.topic()).json_data(&event)),
+            Ok(_) => None,
+            // The client was too slow and missed events, it may want to reload its state
+            Err(skipped) => Some(Ok(warp::sse::Event::default().event("lagged").data(skipped.to_string()))),
+        }))
+        .take_until(async move { shutdown.wait().await });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)).into_response())
+}
+
+// Current display name of a member who made their profile public. Only the cache is asked, anonymous
+// requests must not use up the bot's discord rate limit; without it the name stored at opt-in is shown.
+fn cached_display_name(state: &AppState, discordid: i64) -> Option<String> {
+    let guild = serenity::GuildId::new(state.config.get().discord.guild);
+    let user = serenity::UserId::new(discordid as u64);
+    state.cache.guild(guild)
+        .and_then(|guild| guild.members.get(&user).map(|member| member.display_name().to_string()))
+}
+
+pub async fn public_leaderboard(query: models::LeaderboardQuery, if_none_match: Option<String>, state: AppState) -> Result<warp::reply::Response, warp::Rejection> {
+    let config = state.config.get();
+    let public = &config.api.public;
+    let period = match &query.season {
+        Some(name) => match config.api.season(name).map(|season| season.period()) {
+            Some(Ok(period)) => Some(period),
+            _ => return Ok(error_reply(warp::http::StatusCode::NOT_FOUND, format!("Unknown season \"{}\"", name)).into_response()),
+        },
+        None => None,
+    };
+    let per_page = query.per_page.unwrap_or(public.page_size).clamp(1, public.max_page_size);
+    let page = query.page.unwrap_or(1).max(1);
+    let offset = (page as i64 - 1) * per_page as i64;
+
+    let filter = RankingFilter { track: query.track, car: query.car, period };
+    let (mut entries, total) = match state.database.cutup_rankings(&filter, per_page as i64, offset).await {
+        Ok(rankings) => rankings,
+        Err(e) => {
+            log::error!("Failed to fetch the cutup leaderboard: {}", e);
+            return Ok(error_reply(warp::http::StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch the leaderboard".to_string()).into_response())
+        },
+    };
+    for entry in entries.iter_mut() {
+        if let Some(discordid) = entry.discordid {
+            entry.name = cached_display_name(&state, discordid).or(entry.name.take());
+        }
+    }
+
+    let page = models::LeaderboardPage { season: query.season, page, per_page, total, entries };
+    Ok(caching::cached_json(&page, if_none_match.as_deref(), public.cache_max_age))
+}
+
+pub async fn public_profile(steamid: i64, if_none_match: Option<String>, state: AppState) -> Result<warp::reply::Response, warp::Rejection> {
+    let mut profile = match state.database.player_profile(steamid).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return Ok(error_reply(warp::http::StatusCode::NOT_FOUND, "Unknown player".to_string()).into_response()),
+        Err(e) => {
+            log::error!("Failed to fetch the profile of {}: {}", steamid, e);
+            return Ok(error_reply(warp::http::StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch the profile".to_string()).into_response())
+        },
+    };
+    if let Some(discordid) = profile.discordid {
+        profile.name = cached_display_name(&state, discordid).or(profile.name.take());
+    }
+    Ok(caching::cached_json(&profile, if_none_match.as_deref(), state.config.get().api.public.cache_max_age))
+}
+
+pub async fn public_tracks(if_none_match: Option<String>, state: AppState) -> Result<warp::reply::Response, warp::Rejection> {
+    match state.database.tracks().await {
+        Ok(tracks) => Ok(caching::cached_json(
+            &models::TrackList { tracks },
+            if_none_match.as_deref(),
+            state.config.get().api.public.cache_max_age,
+        )),
+        Err(e) => {
+            log::error!("Failed to fetch tracks: {}", e);
+            Ok(error_reply(warp::http::StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch tracks".to_string()).into_response())
+        },
+    }
+}
+
+pub async fn public_seasons(if_none_match: Option<String>, state: AppState) -> Result<warp::reply::Response, warp::Rejection> {
+    let config = state.config.get();
+    let now = std::time::SystemTime::now();
+    let seasons = config.api.public.seasons.iter().map(|season| models::SeasonInfo {
+        name: season.name.clone(),
+        start: season.start.clone(),
+        end: season.end.clone(),
+        current: season.period().map_or(false, |(start, end)| start <= now && now < end),
+    }).collect();
+    Ok(caching::cached_json(&models::SeasonList { seasons }, if_none_match.as_deref(), config.api.public.cache_max_age))
+}
+
 // pub async fn fetch_lap_time(data: models::ScoreRequest, state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
 //     dbg!("Lap time request: {:?}", &data);
     
@@ -152,10 +545,103 @@ pub async fn update_driver_stats(data: models::UpdateDriverStatsRequest, state:
 //     }
 // }
 
+pub async fn prometheus_metrics() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::with_header(
+        metrics::render(),
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
+pub async fn openapi() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&*openapi::SPEC))
+}
+
+pub async fn docs() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::html(openapi::DOCS))
+}
+
+// The vendored Swagger UI files loaded by /docs
+pub async fn docs_asset((content_type, body): (&'static str, &'static str)) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::with_header(body, "content-type", content_type))
+}
+
 pub async fn heartbeat() -> Result<impl warp::Reply, warp::Rejection> {
     let response = models::DefaultResponse {
         status: "OK".to_string(),
         message: "Alive".to_string(),
+        request_id: None,
     };
     Ok(warp::reply::json(&response))
-}
\ No newline at end of file
+}
+
+// The process is up and serving requests, used to decide on restarts
+pub async fn health_live() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&models::HealthResponse {
+        status: "live".to_string(),
+        components: BTreeMap::new(),
+    }))
+}
+
+fn component(ok: bool, message: String) -> models::ComponentHealth {
+    models::ComponentHealth {
+        status: if ok { "ok" } else { "failing" }.to_string(),
+        message,
+    }
+}
+
+// Checks every dependency, answers 503 if one of them is failing
+pub async fn health_ready(state: AppState) -> Result<impl warp::Reply, warp::Rejection> {
+    let config = state.config.get();
+    let mut components = BTreeMap::new();
+
+    let started = Instant::now();
+    let database = match tokio::time::timeout(Duration::from_secs(5), state.database.query_one("SELECT 1", &[])).await {
+        Ok(Ok(_)) => component(true, format!("Round trip took {}ms", started.elapsed().as_millis())),
+        Ok(Err(e)) => component(false, e.to_string()),
+        Err(_) => component(false, "Query timed out".to_string()),
+    };
+    components.insert("database".to_string(), database);
+
+    // Without a gateway connection (--api-only) there is no cache or leaderboard loop to check
+    if let Some(shard_manager) = &state.shard_manager {
+        let runners = shard_manager.runners.lock().await;
+        let disconnected: Vec<String> = runners.iter()
+            .filter(|(_, runner)| runner.stage != serenity::ConnectionStage::Connected)
+            .map(|(id, runner)| format!("shard {} is {}", id, runner.stage))
+            .collect();
+        let gateway = if runners.is_empty() {
+            component(false, "No shards running".to_string())
+        } else if disconnected.is_empty() {
+            component(true, format!("{} shard(s) connected", runners.len()))
+        } else {
+            component(false, disconnected.join(", "))
+        };
+        drop(runners);
+        components.insert("gateway".to_string(), gateway);
+
+        let guild = serenity::GuildId::new(config.discord.guild);
+        let cache = match state.cache.guild(guild) {
+            Some(_) => component(true, format!("Guild {} is cached", guild)),
+            None => component(false, format!("Guild {} is not in the cache", guild)),
+        };
+        components.insert("cache".to_string(), cache);
+
+        // Allow a few missed updates, a single update can take a while on big guilds
+        let max_age = Duration::from_secs(config.discord.leaderboard_interval * 3 + 60);
+        let leaderboards = match state.health.leaderboards_age() {
+            Some(age) if age <= max_age => component(true, format!("Updated {}s ago", age.as_secs())),
+            Some(age) => component(false, format!("Last update was {}s ago", age.as_secs())),
+            None => component(false, "No update finished yet".to_string()),
+        };
+        components.insert("leaderboards".to_string(), leaderboards);
+    }
+
+    let ready = components.values().all(|component| component.status == "ok");
+    let response = models::HealthResponse {
+        status: if ready { "ready" } else { "not_ready" }.to_string(),
+        components,
+    };
+    let code = if ready { warp::http::StatusCode::OK } else { warp::http::StatusCode::SERVICE_UNAVAILABLE };
+    Ok(warp::reply::with_status(warp::reply::json(&response), code))
+}
@@ -1,18 +1,49 @@
 pub mod models;
 pub mod config;
 pub mod routes;
+pub mod v1;
 pub mod handlers;
+pub mod policy;
+pub mod openapi;
+pub mod ratelimit;
+pub mod server;
+pub mod tls;
 mod errors;
+mod caching;
 
+use std::time::Duration;
+use sha2::{Digest, Sha256};
 use warp::Filter;
+use warp::http::StatusCode;
 use routes::*; // Import the route functions
 
 // This function will combine all the routes and return them as a single filter
 pub fn combined_routes(app_state: models::AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    check_steamid_route(app_state.clone())
+    v1::routes(app_state.clone())
+        .or(check_steamid_route(app_state.clone()))
         .or(cutup_route(app_state.clone()))
         .or(insert_cutup_route(app_state.clone()))
         .or(update_driver_stats_route(app_state.clone()))
+        .or(server_heartbeat_route(app_state.clone()))
         .or(heartbeat_route())
+        .or(health_live_route())
+        .or(health_ready_route(app_state.clone()))
+        .or(metrics_route())
+        .or(openapi_route())
+        .or(docs_route())
+        .or(docs_assets_route())
         .recover(errors::handle_rejection)
-}
\ No newline at end of file
+}
+
+// Counts every request under the path of its route, e.g. /v1/players/{steamid}/cutup. Requests no route
+// matched share one label so scanners can't blow up the metrics.
+fn record_request(route: Option<&str>, status: StatusCode, elapsed: Duration) {
+    let route = route.unwrap_or("unmatched");
+    crate::metrics::API_REQUESTS.with_label_values(&[route, status.as_str()]).inc();
+    crate::metrics::API_REQUEST_DURATION.with_label_values(&[route]).observe(elapsed.as_secs_f64());
+}
+
+// Identifies an API key in logs without revealing it
+pub fn key_fingerprint(key: &str) -> String {
+    Sha256::digest(key.as_bytes())[..4].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -1,9 +1,18 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::fmt;
 
-use crate::Config;
+use crate::config::SharedConfig;
 use crate::database::Database;
+use crate::discord::announcements::Announcer;
+use super::ratelimit::RateLimiter;
+use crate::status::{Player, ServerStatuses};
+use crate::health::Health;
+use crate::bus::EventBus;
+use crate::shutdown::Shutdown;
+use crate::database::ingest::{IngestEvent, IngestResult};
+use crate::database::rankings::{RankedScore, Track};
 use serenity::http::Http;
 use serenity::Cache;
 use warp::reject::Reject;
@@ -14,14 +23,24 @@ use poise::serenity_prelude as serenity;
 pub struct AppState {
     pub http: Arc<Http>,
     pub cache: Arc<Cache>,
+    // Not set when running without a gateway connection (--api-only)
+    pub shard_manager: Option<Arc<serenity::ShardManager>>,
+    pub health: Arc<Health>,
     pub database: Arc<Database>,
-    pub config: Config,
+    pub servers: Arc<ServerStatuses>,
+    pub announcer: Arc<Announcer>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub bus: Arc<EventBus>,
+    pub config: Arc<SharedConfig>,
+    // Ends the event feed streams, which would otherwise keep the server from shutting down
+    pub shutdown: Shutdown,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CheckSteamid {
-    pub roles: Vec<i64>,
     pub steamid: i64,
+    pub policy: Option<String>,
+    pub roles: Option<Vec<i64>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,6 +56,8 @@ pub struct InsertScoreRequest {
     pub track: String,
     pub car: String,
     pub score: i64,
+    // Optional, retries with the same id get the first result back instead of being applied again
+    pub event_id: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,6 +68,108 @@ pub struct UpdateDriverStatsRequest {
     pub avgspeed: i64,
     pub collisions: i64,
     pub distance: f64,
+    // Optional, retries with the same id get the first result back instead of being applied again
+    pub event_id: Option<String>,
+}
+
+// Query of GET /v1/players/{steamid}/cutup
+#[derive(Debug, Deserialize)]
+pub struct CutupQuery {
+    pub track: String,
+    pub car: String,
+}
+
+// Body of POST /v1/players/{steamid}/cutup
+#[derive(Debug, Deserialize)]
+pub struct CutupSubmission {
+    pub track: String,
+    pub car: String,
+    pub score: i64,
+    // Optional, retries with the same id get the first result back instead of being applied again
+    pub event_id: Option<String>,
+}
+
+// Body of POST /v1/players/{steamid}/stats
+#[derive(Debug, Deserialize)]
+pub struct DriverStatsSubmission {
+    pub track: String,
+    pub time: i64,
+    pub avgspeed: i64,
+    pub collisions: i64,
+    pub distance: f64,
+    // Optional, retries with the same id get the first result back instead of being applied again
+    pub event_id: Option<String>,
+}
+
+// Body of POST /v1/ingest
+#[derive(Debug, Deserialize)]
+pub struct IngestRequest {
+    pub events: Vec<IngestEvent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestResponse {
+    pub status: String,
+    // The batch was already ingested under the same idempotency key, these are the stored results
+    pub replayed: bool,
+    pub results: Vec<IngestResult>,
+}
+
+// Query of GET /v1/events
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    // Comma separated, every topic if not set
+    pub topics: Option<String>,
+    // Alternative to the x-api-key header for clients which can't set headers
+    pub key: Option<String>,
+}
+
+// Query of GET /v1/public/leaderboards/cutup
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardQuery {
+    pub track: Option<String>,
+    pub car: Option<String>,
+    // Name of a season from the config, all time if not set
+    pub season: Option<String>,
+    // Starts at 1
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderboardPage {
+    pub season: Option<String>,
+    pub page: u32,
+    pub per_page: u32,
+    // Entries on all pages
+    pub total: i64,
+    pub entries: Vec<RankedScore>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackList {
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeasonInfo {
+    pub name: String,
+    pub start: String,
+    pub end: String,
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeasonList {
+    pub seasons: Vec<SeasonInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServerHeartbeat {
+    pub name: String,
+    pub track: String,
+    pub players: Vec<Player>,
+    pub capacity: u32,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -58,6 +181,37 @@ pub struct ScoreResponse {
 pub struct DefaultResponse {
     pub status: String,
     pub message: String,
+    // Only set on errors, like in ErrorResponse
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckSteamidResponse {
+    pub status: String,
+    pub message: String,
+    pub policy: Option<String>,
+    pub rule: Option<String>,
+}
+
+// Body of failed requests, the request id lets game server admins find the matching log lines
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub status: String,
+    pub message: String,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComponentHealth {
+    pub status: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub components: BTreeMap<String, ComponentHealth>,
 }
 
 #[derive(Debug)]
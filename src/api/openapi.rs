@@ -0,0 +1,878 @@
+use once_cell::sync::Lazy;
+use serde_json::{json, Map, Value};
+
+use super::models::*;
+use crate::status::Player;
+use crate::bus::{self, BotEvent};
+use crate::database::ingest::{IngestEvent, IngestResult};
+use crate::database::rankings::{PlayerProfile, RankedScore};
+
+// Types sent or received by the API, described as JSON schemas for the OpenAPI document
+pub trait Schema {
+    const NAME: &'static str;
+    fn schema() -> Value;
+}
+
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({ "type": "object", "required": required, "properties": properties })
+}
+
+fn int64(description: &str) -> Value {
+    json!({ "type": "integer", "format": "int64", "description": description })
+}
+
+fn string(description: &str) -> Value {
+    json!({ "type": "string", "description": description })
+}
+
+fn nullable(mut schema: Value) -> Value {
+    schema["nullable"] = json!(true);
+    schema
+}
+
+fn event_id() -> Value {
+    nullable(string("Unique id of the submission, retries with the same id within `api.dedup_ttl` get the first result back"))
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+impl Schema for CheckSteamid {
+    const NAME: &'static str = "CheckSteamid";
+    fn schema() -> Value {
+        object(&["steamid"], json!({
+            "steamid": int64("Steam id of the joining driver"),
+            "policy": nullable(string("Name of an access policy from the config")),
+            "roles": nullable(json!({ "type": "array", "items": int64("Role id"), "description": "Legacy role list, used when no policy is given" })),
+        }))
+    }
+}
+
+impl Schema for CheckSteamidResponse {
+    const NAME: &'static str = "CheckSteamidResponse";
+    fn schema() -> Value {
+        object(&["status", "message"], json!({
+            "status": json!({ "type": "string", "enum": ["OK", "UNAUTHORIZED", "ERROR"] }),
+            "message": string("Reason shown to the driver"),
+            "policy": nullable(string("Policy which was evaluated")),
+            "rule": nullable(string("Rule which decided the outcome, e.g. `any_of:1234567890` or `banned`")),
+        }))
+    }
+}
+
+impl Schema for ScoreRequest {
+    const NAME: &'static str = "ScoreRequest";
+    fn schema() -> Value {
+        object(&["steamid", "track", "car"], json!({
+            "steamid": int64("Steam id of the driver"),
+            "track": string("Track name"),
+            "car": string("Car model"),
+        }))
+    }
+}
+
+impl Schema for ScoreResponse {
+    const NAME: &'static str = "ScoreResponse";
+    fn schema() -> Value {
+        object(&["data"], json!({
+            "data": int64("Personal best of the driver, 0 if there is none"),
+        }))
+    }
+}
+
+impl Schema for InsertScoreRequest {
+    const NAME: &'static str = "InsertScoreRequest";
+    fn schema() -> Value {
+        object(&["steamid", "track", "car", "score"], json!({
+            "steamid": int64("Steam id of the driver"),
+            "track": string("Track name"),
+            "car": string("Car model"),
+            "score": int64("Cutup score, rejected if above `api.max_score`"),
+            "event_id": event_id(),
+        }))
+    }
+}
+
+impl Schema for UpdateDriverStatsRequest {
+    const NAME: &'static str = "UpdateDriverStatsRequest";
+    fn schema() -> Value {
+        object(&["steamid", "track", "time", "avgspeed", "collisions", "distance"], json!({
+            "steamid": int64("Steam id of the driver"),
+            "track": string("Track name"),
+            "time": int64("Time driven"),
+            "avgspeed": int64("Average speed"),
+            "collisions": int64("Number of collisions"),
+            "distance": json!({ "type": "number", "format": "double", "description": "Distance driven" }),
+            "event_id": event_id(),
+        }))
+    }
+}
+
+impl Schema for CutupSubmission {
+    const NAME: &'static str = "CutupSubmission";
+    fn schema() -> Value {
+        object(&["track", "car", "score"], json!({
+            "track": string("Track name"),
+            "car": string("Car model"),
+            "score": int64("Cutup score, rejected if above `api.max_score`"),
+            "event_id": event_id(),
+        }))
+    }
+}
+
+impl Schema for DriverStatsSubmission {
+    const NAME: &'static str = "DriverStatsSubmission";
+    fn schema() -> Value {
+        object(&["track", "time", "avgspeed", "collisions", "distance"], json!({
+            "track": string("Track name"),
+            "time": int64("Time driven"),
+            "avgspeed": int64("Average speed"),
+            "collisions": int64("Number of collisions"),
+            "distance": json!({ "type": "number", "format": "double", "description": "Distance driven" }),
+            "event_id": event_id(),
+        }))
+    }
+}
+
+impl Schema for IngestEvent {
+    const NAME: &'static str = "IngestEvent";
+    fn schema() -> Value {
+        let event = |kind: &str, mut required: Vec<&str>, mut properties: Value| {
+            required.push("type");
+            properties["type"] = json!({ "type": "string", "enum": [kind] });
+            object(&required, properties)
+        };
+        json!({
+            "oneOf": [
+                event("cutup_score", vec!["steamid", "track", "car", "score"], json!({
+                    "steamid": int64("Steam id of the driver"),
+                    "track": string("Track name"),
+                    "car": string("Car model"),
+                    "score": int64("Cutup score, rejected if above `api.max_score`"),
+                    "event_id": event_id(),
+                })),
+                event("driver_stats", vec!["steamid", "track", "time", "avgspeed", "collisions", "distance"], json!({
+                    "steamid": int64("Steam id of the driver"),
+                    "track": string("Track name"),
+                    "time": int64("Time driven"),
+                    "avgspeed": int64("Average speed"),
+                    "collisions": int64("Number of collisions"),
+                    "distance": json!({ "type": "number", "format": "double", "description": "Distance driven" }),
+                    "event_id": event_id(),
+                })),
+                event("lap", vec!["steamid", "track", "car", "time"], json!({
+                    "steamid": int64("Steam id of the driver"),
+                    "track": string("Track name"),
+                    "car": string("Car model"),
+                    "time": int64("Lap time, only the fastest per track and car is kept"),
+                    "event_id": event_id(),
+                })),
+            ],
+            "discriminator": { "propertyName": "type" },
+        })
+    }
+}
+
+impl Schema for IngestRequest {
+    const NAME: &'static str = "IngestRequest";
+    fn schema() -> Value {
+        object(&["events"], json!({
+            "events": json!({ "type": "array", "items": reference(IngestEvent::NAME), "description": "At most `api.max_batch_size` events" }),
+        }))
+    }
+}
+
+impl Schema for IngestResult {
+    const NAME: &'static str = "IngestResult";
+    fn schema() -> Value {
+        object(&["index", "status"], json!({
+            "index": json!({ "type": "integer", "minimum": 0, "description": "Position of the event in the batch" }),
+            "status": json!({ "type": "string", "enum": ["ok", "improved", "not_improved", "rejected", "banned", "error"], "description": "`rejected` for scores above `api.max_score` and tracks the game server isn't allowed" }),
+            "message": nullable(string("Why the event was refused")),
+        }))
+    }
+}
+
+impl Schema for IngestResponse {
+    const NAME: &'static str = "IngestResponse";
+    fn schema() -> Value {
+        object(&["status", "replayed", "results"], json!({
+            "status": string("OK if the batch was stored"),
+            "replayed": json!({ "type": "boolean", "description": "The idempotency key was seen before: events with a final status keep their stored result, `error` ones were applied again" }),
+            "results": json!({ "type": "array", "items": reference(IngestResult::NAME) }),
+        }))
+    }
+}
+
+impl Schema for RankedScore {
+    const NAME: &'static str = "RankedScore";
+    fn schema() -> Value {
+        object(&["rank", "steamid", "track", "car", "score"], json!({
+            "rank": int64("Position, equal scores share one"),
+            "steamid": int64("Steam id of the driver"),
+            "name": nullable(string("Discord display name, only for members who made their profile public")),
+            "track": string("Track name"),
+            "car": string("Car model"),
+            "score": int64("Best cutup score"),
+        }))
+    }
+}
+
+impl Schema for LeaderboardPage {
+    const NAME: &'static str = "LeaderboardPage";
+    fn schema() -> Value {
+        object(&["page", "per_page", "total", "entries"], json!({
+            "season": nullable(string("Season the scores were set in, all time if null")),
+            "page": json!({ "type": "integer", "minimum": 1, "description": "Page number, starting at 1" }),
+            "per_page": json!({ "type": "integer", "minimum": 1, "description": "Entries per page" }),
+            "total": int64("Entries on all pages"),
+            "entries": json!({ "type": "array", "items": reference(RankedScore::NAME) }),
+        }))
+    }
+}
+
+impl Schema for PlayerProfile {
+    const NAME: &'static str = "PlayerProfile";
+    fn schema() -> Value {
+        object(&["steamid", "scores", "laps", "stats"], json!({
+            "steamid": int64("Steam id of the driver"),
+            "name": nullable(string("Discord display name, only for members who made their profile public")),
+            "scores": json!({ "type": "array", "items": object(&["track", "car", "score", "rank"], json!({
+                "track": string("Track name"),
+                "car": string("Car model"),
+                "score": int64("Best cutup score"),
+                "rank": int64("Position on the leaderboard of the track and car"),
+            })) }),
+            "laps": json!({ "type": "array", "items": object(&["track", "car", "time"], json!({
+                "track": string("Track name"),
+                "car": string("Car model"),
+                "time": int64("Fastest lap time"),
+            })) }),
+            "stats": json!({ "type": "array", "items": object(&["track", "time", "avgspeed", "collisions", "distance"], json!({
+                "track": string("Track name"),
+                "time": int64("Time driven"),
+                "avgspeed": int64("Average speed"),
+                "collisions": int64("Number of collisions"),
+                "distance": json!({ "type": "number", "format": "double", "description": "Distance driven" }),
+            })) }),
+        }))
+    }
+}
+
+impl Schema for TrackList {
+    const NAME: &'static str = "TrackList";
+    fn schema() -> Value {
+        object(&["tracks"], json!({
+            "tracks": json!({ "type": "array", "items": object(&["name", "cars"], json!({
+                "name": string("Track name"),
+                "cars": json!({ "type": "array", "items": string("Car model") }),
+            })) }),
+        }))
+    }
+}
+
+impl Schema for SeasonList {
+    const NAME: &'static str = "SeasonList";
+    fn schema() -> Value {
+        object(&["seasons"], json!({
+            "seasons": json!({ "type": "array", "items": object(&["name", "start", "end", "current"], json!({
+                "name": string("Name to pass as `season`"),
+                "start": string("RFC 3339 timestamp of the start"),
+                "end": string("RFC 3339 timestamp of the end, exclusive"),
+                "current": json!({ "type": "boolean", "description": "The season is running" }),
+            })) }),
+        }))
+    }
+}
+
+impl Schema for Player {
+    const NAME: &'static str = "Player";
+    fn schema() -> Value {
+        object(&["name"], json!({
+            "name": string("Player name"),
+            "steamid": nullable(int64("Steam id of the player")),
+        }))
+    }
+}
+
+impl Schema for BotEvent {
+    const NAME: &'static str = "BotEvent";
+    fn schema() -> Value {
+        let event = |kind: &str, mut required: Vec<&str>, mut properties: Value| {
+            required.push("type");
+            properties["type"] = json!({ "type": "string", "enum": [kind] });
+            object(&required, properties)
+        };
+        json!({
+            "oneOf": [
+                event("highscore", vec!["steamid", "track", "car", "score", "rank", "server_record"], json!({
+                    "steamid": int64("Steam id of the driver"),
+                    "track": string("Track name"),
+                    "car": string("Car model"),
+                    "score": int64("New personal best"),
+                    "previous": nullable(int64("Previous personal best")),
+                    "rank": int64("Position on the track leaderboard"),
+                    "server_record": json!({ "type": "boolean", "description": "The score beat the track record" }),
+                })),
+                event("leaderboard", vec!["title", "entries"], json!({
+                    "title": string("Title of the leaderboard"),
+                    "entries": json!({ "type": "array", "items": object(&["discordid", "score"], json!({
+                        "discordid": int64("Discord id of the member"),
+                        "score": int64("Score shown on the leaderboard"),
+                    })) }),
+                })),
+                event("link", vec!["discordid", "steamid"], json!({
+                    "discordid": int64("Discord id of the member"),
+                    "steamid": int64("Linked steam id"),
+                })),
+                event("server_heartbeat", vec!["name", "track", "players", "capacity"], json!({
+                    "name": string("Unique name of the game server"),
+                    "track": string("Current track"),
+                    "players": json!({ "type": "array", "items": reference(Player::NAME) }),
+                    "capacity": json!({ "type": "integer", "format": "int32", "minimum": 0, "description": "Player slots" }),
+                })),
+                event("ban", vec!["id", "steamid", "reason", "scope"], json!({
+                    "id": json!({ "type": "integer", "format": "int32", "description": "Id of the ban" }),
+                    "steamid": int64("Steam id of the banned driver"),
+                    "reason": string("Reason given by the moderator"),
+                    "scope": string("What the ban applies to"),
+                    "expires_at": nullable(json!({ "type": "string", "format": "date-time", "description": "Permanent if null" })),
+                })),
+                event("unban", vec!["steamid", "revoked"], json!({
+                    "steamid": int64("Steam id of the driver"),
+                    "revoked": json!({ "type": "integer", "minimum": 0, "description": "Number of active bans lifted" }),
+                })),
+            ],
+            "discriminator": { "propertyName": "type" },
+        })
+    }
+}
+
+impl Schema for ServerHeartbeat {
+    const NAME: &'static str = "ServerHeartbeat";
+    fn schema() -> Value {
+        object(&["name", "track", "players", "capacity"], json!({
+            "name": string("Unique name of the game server"),
+            "track": string("Current track"),
+            "players": json!({ "type": "array", "items": reference(Player::NAME) }),
+            "capacity": json!({ "type": "integer", "format": "int32", "minimum": 0, "description": "Player slots" }),
+        }))
+    }
+}
+
+impl Schema for DefaultResponse {
+    const NAME: &'static str = "DefaultResponse";
+    fn schema() -> Value {
+        object(&["status", "message"], json!({
+            "status": string("OK on success, e.g. ERROR or BANNED otherwise"),
+            "message": string("Human readable outcome"),
+            "request_id": string("Id of the request in the logs, only sent with ERROR"),
+        }))
+    }
+}
+
+impl Schema for ErrorResponse {
+    const NAME: &'static str = "ErrorResponse";
+    fn schema() -> Value {
+        object(&["status", "message"], json!({
+            "status": string("HTTP status code"),
+            "message": string("What went wrong"),
+            "request_id": nullable(string("Id of the request in the logs, also sent as the x-request-id header")),
+        }))
+    }
+}
+
+impl Schema for ComponentHealth {
+    const NAME: &'static str = "ComponentHealth";
+    fn schema() -> Value {
+        object(&["status", "message"], json!({
+            "status": json!({ "type": "string", "enum": ["ok", "failing"] }),
+            "message": string("Details of the check"),
+        }))
+    }
+}
+
+impl Schema for HealthResponse {
+    const NAME: &'static str = "HealthResponse";
+    fn schema() -> Value {
+        object(&["status", "components"], json!({
+            "status": json!({ "type": "string", "enum": ["live", "ready", "not_ready"] }),
+            "components": json!({
+                "type": "object",
+                "additionalProperties": reference(ComponentHealth::NAME),
+                "description": "Checks by name: database, gateway, cache and leaderboards",
+            }),
+        }))
+    }
+}
+
+// Collects the operations and the schemas they use
+#[derive(Default)]
+struct Spec {
+    paths: Map<String, Value>,
+    schemas: Map<String, Value>,
+}
+
+impl Spec {
+    fn register<T: Schema>(&mut self) -> Value {
+        self.schemas.insert(T::NAME.to_string(), T::schema());
+        reference(T::NAME)
+    }
+
+    fn operation(&mut self, path: &str, method: &str, operation: Value) {
+        let item = self.paths.entry(path.to_string()).or_insert_with(|| json!({}));
+        item[method] = operation;
+    }
+
+    fn json(schema: Value, description: &str) -> Value {
+        json!({ "description": description, "content": { "application/json": { "schema": schema } } })
+    }
+
+    // JSON route of the game server API, with the errors every such route can answer with
+    fn post<Req: Schema, Res: Schema>(&mut self, path: &str, summary: &str) {
+        let request = self.register::<Req>();
+        let response = self.register::<Res>();
+        let error = self.register::<ErrorResponse>();
+        self.operation(path, "post", json!({
+            "summary": summary,
+            "requestBody": { "required": true, "content": { "application/json": { "schema": request } } },
+            "responses": {
+                "200": Spec::json(response, "Outcome of the request"),
+                "400": Spec::json(error.clone(), "The body doesn't match the schema"),
+                "413": Spec::json(error.clone(), "The body is bigger than `api.max_body_size`"),
+                "429": Spec::json(error.clone(), "Rate limited, retry after the `Retry-After` header"),
+                "500": Spec::json(error, "Internal error"),
+            },
+        }));
+    }
+
+    fn get<Res: Schema>(&mut self, path: &str, summary: &str) {
+        let response = self.register::<Res>();
+        self.operation(path, "get", json!({
+            "summary": summary,
+            "responses": { "200": Spec::json(response, "OK") },
+        }));
+    }
+
+    fn parameters(&mut self, path: &str, method: &str, parameters: Value) {
+        self.paths[path][method]["parameters"] = parameters;
+    }
+
+    // Public routes answer with an ETag, a 304 if If-None-Match has it, and may be rate limited
+    fn cached(&mut self, path: &str) {
+        let error = self.register::<ErrorResponse>();
+        let responses = &mut self.paths[path]["get"]["responses"];
+        responses["304"] = json!({ "description": "Not modified since the ETag in If-None-Match" });
+        responses["429"] = Spec::json(error, "Rate limited, retry after the `Retry-After` header");
+    }
+
+    // Writes identify the game server by its `x-api-key`, see the registry managed with /gameserver
+    fn authenticated(&mut self, path: &str) {
+        let error = self.register::<ErrorResponse>();
+        let responses = &mut self.paths[path]["post"]["responses"];
+        responses["401"] = Spec::json(error.clone(), "No key of a registered game server, required once a server is registered unless `api.require_server_key` is false");
+        responses["403"] = Spec::json(error, "The game server is disabled");
+    }
+
+    // Legacy routes which have a /v1 successor
+    fn deprecate(&mut self, path: &str, method: &str) {
+        self.paths[path][method]["deprecated"] = json!(true);
+    }
+
+    fn finish(self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "swim> server API",
+                "version": env!("CARGO_PKG_VERSION"),
+                "description": "API used by the game server plugins. Every response carries an `x-request-id` header.",
+            },
+            "paths": self.paths,
+            "components": { "schemas": self.schemas },
+        })
+    }
+}
+
+fn path_parameter(name: &str, description: &str) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "schema": { "type": "integer", "format": "int64" }, "description": description })
+}
+
+fn query_parameter(name: &str, description: &str) -> Value {
+    json!({ "name": name, "in": "query", "required": true, "schema": { "type": "string" }, "description": description })
+}
+
+fn build() -> Value {
+    let mut spec = Spec::default();
+    let steamid = path_parameter("steamid", "Steam id of the driver");
+    spec.post::<CheckSteamid, CheckSteamidResponse>("/v1/whitelist/check", "Check whether a driver may join a server");
+    let unavailable = spec.register::<CheckSteamidResponse>();
+    spec.paths["/v1/whitelist/check"]["post"]["responses"]["503"] = Spec::json(unavailable.clone(), "Bans or the link could not be looked up, the driver isn't let in");
+    spec.get::<ScoreResponse>("/v1/players/{steamid}/cutup", "Fetch the cutup personal best of a driver");
+    spec.parameters("/v1/players/{steamid}/cutup", "get", json!([
+        steamid.clone(),
+        query_parameter("track", "Track name"),
+        query_parameter("car", "Car model"),
+    ]));
+    spec.post::<CutupSubmission, DefaultResponse>("/v1/players/{steamid}/cutup", "Submit a cutup score");
+    spec.parameters("/v1/players/{steamid}/cutup", "post", json!([steamid.clone()]));
+    spec.post::<DriverStatsSubmission, DefaultResponse>("/v1/players/{steamid}/stats", "Add to the stats of a driver");
+    spec.parameters("/v1/players/{steamid}/stats", "post", json!([steamid]));
+    spec.post::<ServerHeartbeat, DefaultResponse>("/v1/servers/heartbeat", "Report the state of a game server");
+    spec.post::<IngestRequest, IngestResponse>("/v1/ingest", "Submit a batch of scores, driver stats and laps in one transaction");
+    spec.parameters("/v1/ingest", "post", json!([{
+        "name": "Idempotency-Key",
+        "in": "header",
+        "required": false,
+        "schema": { "type": "string" },
+        "description": "Retries with the same key get the stored results instead of ingesting the batch again",
+    }]));
+    spec.register::<IngestEvent>();
+    spec.register::<IngestResult>();
+    for path in ["/v1/players/{steamid}/cutup", "/v1/players/{steamid}/stats", "/v1/servers/heartbeat", "/v1/ingest"] {
+        spec.authenticated(path);
+    }
+    let event = spec.register::<BotEvent>();
+    let error = spec.register::<ErrorResponse>();
+    spec.operation("/v1/events", "get", json!({
+        "summary": "Stream bot events as server-sent events",
+        "description": format!(
+            "Every event is sent with its topic ({}) as the event name and a JSON `BotEvent` as data. An event named \
+            `lagged` carries the number of events a slow client missed. Requires a key from `api.feed_keys`.",
+            bus::TOPICS.join(", "),
+        ),
+        "parameters": [
+            {
+                "name": "topics",
+                "in": "query",
+                "required": false,
+                "schema": { "type": "string" },
+                "description": "Comma separated topics, every topic if not set",
+            },
+            {
+                "name": "key",
+                "in": "query",
+                "required": false,
+                "schema": { "type": "string" },
+                "description": "API key, for clients which can't set the `x-api-key` header",
+            },
+        ],
+        "responses": {
+            "200": { "description": "Event stream", "content": { "text/event-stream": { "schema": event } } },
+            "400": Spec::json(error.clone(), "Unknown topic"),
+            "401": Spec::json(error.clone(), "Missing or unknown API key"),
+            "403": Spec::json(error.clone(), "The feed is disabled"),
+            "429": Spec::json(error, "Rate limited, retry after the `Retry-After` header"),
+        },
+    }));
+
+    // Read-only routes for the website, without API key
+    let if_none_match = json!({
+        "name": "If-None-Match",
+        "in": "header",
+        "required": false,
+        "schema": { "type": "string" },
+        "description": "ETag of a previous response, answered with 304 if nothing changed",
+    });
+    let optional_query = |name: &str, kind: &str, description: &str| json!({
+        "name": name, "in": "query", "required": false, "schema": { "type": kind }, "description": description,
+    });
+    spec.get::<LeaderboardPage>("/v1/public/leaderboards/cutup", "Ranked cutup scores");
+    spec.parameters("/v1/public/leaderboards/cutup", "get", json!([
+        optional_query("track", "string", "Only scores on this track"),
+        optional_query("car", "string", "Only scores with this car"),
+        optional_query("season", "string", "Only scores set in this season, all time if not set"),
+        optional_query("page", "integer", "Page number, starting at 1"),
+        optional_query("per_page", "integer", "Entries per page, at most `api.public.max_page_size`"),
+        if_none_match.clone(),
+    ]));
+    spec.get::<PlayerProfile>("/v1/public/players/{steamid}", "Best scores, laps and stats of a driver");
+    spec.parameters("/v1/public/players/{steamid}", "get", json!([
+        path_parameter("steamid", "Steam id of the driver"),
+        if_none_match.clone(),
+    ]));
+    spec.get::<TrackList>("/v1/public/tracks", "Tracks and the cars driven on them");
+    spec.parameters("/v1/public/tracks", "get", json!([if_none_match.clone()]));
+    spec.get::<SeasonList>("/v1/public/seasons", "Seasons which can be passed to the leaderboard");
+    spec.parameters("/v1/public/seasons", "get", json!([if_none_match]));
+    for path in ["/v1/public/leaderboards/cutup", "/v1/public/players/{steamid}", "/v1/public/tracks", "/v1/public/seasons"] {
+        spec.cached(path);
+    }
+    let error = spec.register::<ErrorResponse>();
+    spec.paths["/v1/public/leaderboards/cutup"]["get"]["responses"]["404"] = Spec::json(error.clone(), "Unknown season");
+    spec.paths["/v1/public/players/{steamid}"]["get"]["responses"]["404"] = Spec::json(error, "Nothing is known about the driver");
+
+    // Legacy aliases, answered with a Deprecation header and a Link to their successor
+    spec.post::<CheckSteamid, CheckSteamidResponse>("/checksteamid", "Use /v1/whitelist/check");
+    spec.paths["/checksteamid"]["post"]["responses"]["503"] = Spec::json(unavailable, "Bans or the link could not be looked up, the driver isn't let in");
+    spec.post::<ScoreRequest, ScoreResponse>("/fetch_cutup_score", "Use GET /v1/players/{steamid}/cutup");
+    spec.post::<InsertScoreRequest, DefaultResponse>("/insert_cutup_score", "Use POST /v1/players/{steamid}/cutup");
+    spec.post::<UpdateDriverStatsRequest, DefaultResponse>("/update_driver_stats", "Use /v1/players/{steamid}/stats");
+    spec.post::<ServerHeartbeat, DefaultResponse>("/server_heartbeat", "Use /v1/servers/heartbeat");
+    for path in ["/checksteamid", "/fetch_cutup_score", "/insert_cutup_score", "/update_driver_stats", "/server_heartbeat"] {
+        spec.deprecate(path, "post");
+    }
+    for path in ["/insert_cutup_score", "/update_driver_stats", "/server_heartbeat"] {
+        spec.authenticated(path);
+    }
+
+    spec.get::<DefaultResponse>("/heartbeat", "Always answers \"Alive\"");
+    spec.get::<HealthResponse>("/health/live", "Liveness probe");
+    spec.get::<HealthResponse>("/health/ready", "Readiness probe, checks every dependency");
+    let unavailable = spec.register::<HealthResponse>();
+    spec.paths["/health/ready"]["get"]["responses"]["503"] = Spec::json(unavailable, "A dependency is failing");
+    spec.register::<Player>();
+    spec.register::<ComponentHealth>();
+
+    spec.operation("/metrics", "get", json!({
+        "summary": "Prometheus metrics",
+        "responses": { "200": { "description": "Metrics in the Prometheus text format", "content": { "text/plain": {} } } },
+    }));
+    spec.operation("/openapi.json", "get", json!({
+        "summary": "This document",
+        "responses": { "200": { "description": "OpenAPI 3 document", "content": { "application/json": {} } } },
+    }));
+    spec.operation("/docs", "get", json!({
+        "summary": "Rendered documentation of this document",
+        "responses": { "200": { "description": "HTML page", "content": { "text/html": {} } } },
+    }));
+    spec.operation("/docs/swagger-ui-bundle.js", "get", json!({
+        "summary": "Script of the rendered documentation",
+        "responses": { "200": { "description": "Swagger UI bundle", "content": { "text/javascript": {} } } },
+    }));
+    spec.operation("/docs/swagger-ui.css", "get", json!({
+        "summary": "Stylesheet of the rendered documentation",
+        "responses": { "200": { "description": "Swagger UI stylesheet", "content": { "text/css": {} } } },
+    }));
+    spec.finish()
+}
+
+// Built once, the document only changes with the code
+pub static SPEC: Lazy<Value> = Lazy::new(build);
+
+// Swagger UI 5.17.14 is vendored in assets/swagger-ui and served by the API itself, /docs loads nothing from elsewhere
+pub const DOCS: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+    <title>swim> server API</title>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <link rel="stylesheet" href="/docs/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="/docs/swagger-ui-bundle.js"></script>
+    <script>
+        window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+</body>
+</html>
+"##;
+
+pub const DOCS_SCRIPT: &str = include_str!("assets/swagger-ui/swagger-ui-bundle.js");
+pub const DOCS_STYLE: &str = include_str!("assets/swagger-ui/swagger-ui.css");
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::database::rankings::{ProfileLap, ProfileScore, Track, TrackStats};
+    use crate::bus::LeaderboardEntry;
+    use crate::status::ServerStatus;
+    use crate::api::routes::ROUTES;
+
+    #[test]
+    fn every_route_is_documented() {
+        for route in ROUTES.iter() {
+            let method = route.method.as_str().to_lowercase();
+            assert!(SPEC["paths"][route.path][&method].is_object(), "{} {} is missing from the OpenAPI document", route.method, route.path);
+        }
+        for (path, operations) in SPEC["paths"].as_object().unwrap() {
+            for method in operations.as_object().unwrap().keys() {
+                let served = ROUTES.iter().any(|route| route.path == path.as_str() && route.method.as_str().eq_ignore_ascii_case(method));
+                assert!(served, "{} {} is documented but not routed", method, path);
+            }
+        }
+    }
+
+    #[test]
+    fn every_reference_has_a_schema() {
+        let document = SPEC.to_string();
+        let schemas = SPEC["components"]["schemas"].as_object().unwrap();
+        for reference in document.split("#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "schema {} is referenced but not defined", name);
+        }
+    }
+
+    // Follows a reference to a schema of the document
+    fn resolve(schema: &Value) -> &Value {
+        match schema["$ref"].as_str() {
+            Some(reference) => &SPEC["components"]["schemas"][reference.trim_start_matches("#/components/schemas/")],
+            None => schema,
+        }
+    }
+
+    // A value for every documented property, `required_only` leaves out the optional ones
+    fn sample(schema: &Value, required_only: bool) -> Value {
+        let schema = resolve(schema);
+        if let Some(variant) = schema["oneOf"].get(0) {
+            return sample(variant, required_only)
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            return values[0].clone()
+        }
+        match schema["type"].as_str() {
+            Some("object") => {
+                let required = schema["required"].as_array().unwrap();
+                schema["properties"].as_object().unwrap().iter()
+                    .filter(|(name, _)| !required_only || required.iter().any(|required| required == *name))
+                    .map(|(name, property)| (name.clone(), sample(property, required_only)))
+                    .collect::<serde_json::Map<_, _>>()
+                    .into()
+            },
+            Some("array") => json!([sample(&schema["items"], required_only)]),
+            Some("integer") => json!(1),
+            Some("number") => json!(1.5),
+            Some("boolean") => json!(true),
+            Some("string") => json!("sample"),
+            _ => panic!("no sample for schema {}", schema),
+        }
+    }
+
+    // Every serialized field is documented and every documented one is serialized
+    fn assert_documented(schema: &Value, value: &Value, path: &str) {
+        let schema = resolve(schema);
+        if let Some(variants) = schema["oneOf"].as_array() {
+            let variant = variants.iter()
+                .find(|variant| variant["properties"]["type"]["enum"][0] == value["type"])
+                .unwrap_or_else(|| panic!("{}: type {} is not documented", path, value["type"]));
+            return assert_documented(variant, value, path)
+        }
+        match value {
+            Value::Object(fields) => match schema["properties"].as_object() {
+                Some(properties) => {
+                    for name in properties.keys() {
+                        assert!(fields.contains_key(name), "{}.{} is documented but not serialized", path, name);
+                    }
+                    for (name, field) in fields {
+                        let property = properties.get(name).unwrap_or_else(|| panic!("{}.{} is not documented", path, name));
+                        assert_documented(property, field, &format!("{}.{}", path, name));
+                    }
+                },
+                None => for (name, field) in fields {
+                    assert_documented(&schema["additionalProperties"], field, &format!("{}.{}", path, name));
+                },
+            },
+            Value::Array(items) => for item in items {
+                assert_documented(&schema["items"], item, &format!("{}[]", path));
+            },
+            _ => {},
+        }
+    }
+
+    fn assert_response<T: Schema + Serialize>(sample: T) {
+        assert_documented(&T::schema(), &serde_json::to_value(&sample).unwrap(), T::NAME);
+    }
+
+    // The full sample and one without the optional properties have to be accepted
+    fn assert_request<T: Schema + DeserializeOwned>() {
+        for required_only in [false, true] {
+            let body = sample(&T::schema(), required_only);
+            if let Err(e) = serde_json::from_value::<T>(body.clone()) {
+                panic!("{} rejects the documented body {}: {}", T::NAME, body, e);
+            }
+        }
+    }
+
+    #[test]
+    fn requests_match_their_schemas() {
+        assert_request::<CheckSteamid>();
+        assert_request::<ScoreRequest>();
+        assert_request::<InsertScoreRequest>();
+        assert_request::<UpdateDriverStatsRequest>();
+        assert_request::<CutupSubmission>();
+        assert_request::<DriverStatsSubmission>();
+        assert_request::<ServerHeartbeat>();
+        assert_request::<IngestRequest>();
+        for variant in IngestEvent::schema()["oneOf"].as_array().unwrap() {
+            for required_only in [false, true] {
+                let body = sample(variant, required_only);
+                assert!(serde_json::from_value::<IngestEvent>(body.clone()).is_ok(), "IngestEvent rejects the documented body {}", body);
+            }
+        }
+    }
+
+    #[test]
+    fn responses_match_their_schemas() {
+        let ranked = || RankedScore {
+            rank: 1,
+            steamid: 76561197960287930,
+            name: Some("driver".to_string()),
+            discordid: Some(1234567890),
+            track: "shutoko".to_string(),
+            car: "ae86".to_string(),
+            score: 1000,
+        };
+        let result = || IngestResult { index: 0, status: "error".to_string(), message: Some("failed".to_string()) };
+
+        assert_response(CheckSteamidResponse {
+            status: "OK".to_string(),
+            message: "Authorized".to_string(),
+            policy: Some("members".to_string()),
+            rule: Some("any_of:1234567890".to_string()),
+        });
+        assert_response(ScoreResponse { data: 1000 });
+        assert_response(result());
+        assert_response(IngestResponse { status: "OK".to_string(), replayed: true, results: vec![result()] });
+        assert_response(ranked());
+        assert_response(LeaderboardPage { season: Some("s1".to_string()), page: 1, per_page: 50, total: 1, entries: vec![ranked()] });
+        assert_response(PlayerProfile {
+            steamid: 76561197960287930,
+            name: Some("driver".to_string()),
+            discordid: Some(1234567890),
+            scores: vec![ProfileScore { track: "shutoko".to_string(), car: "ae86".to_string(), score: 1000, rank: 1 }],
+            laps: vec![ProfileLap { track: "shutoko".to_string(), car: "ae86".to_string(), time: 90000 }],
+            stats: vec![TrackStats { track: "shutoko".to_string(), time: 3600, avgspeed: 120, collisions: 2, distance: 100.5 }],
+        });
+        assert_response(TrackList { tracks: vec![Track { name: "shutoko".to_string(), cars: vec!["ae86".to_string()] }] });
+        assert_response(SeasonList { seasons: vec![SeasonInfo {
+            name: "s1".to_string(),
+            start: "2024-01-01T00:00:00Z".to_string(),
+            end: "2024-04-01T00:00:00Z".to_string(),
+            current: false,
+        }] });
+        assert_response(Player { name: "driver".to_string(), steamid: Some(76561197960287930) });
+        assert_response(DefaultResponse { status: "ERROR".to_string(), message: "Failed".to_string(), request_id: Some("id".to_string()) });
+        assert_response(ErrorResponse { status: "500".to_string(), message: "Failed".to_string(), request_id: Some("id".to_string()) });
+        assert_response(HealthResponse {
+            status: "ready".to_string(),
+            components: BTreeMap::from([("database".to_string(), ComponentHealth { status: "ok".to_string(), message: "Connected".to_string() })]),
+        });
+        for event in [
+            BotEvent::Highscore {
+                steamid: 76561197960287930, track: "shutoko".to_string(), car: "ae86".to_string(), score: 1000,
+                previous: Some(900), rank: 1, server_record: true,
+            },
+            BotEvent::Leaderboard { title: "Cutup".to_string(), entries: vec![LeaderboardEntry { discordid: 1234567890, score: 1000 }] },
+            BotEvent::Link { discordid: 1234567890, steamid: 76561197960287930 },
+            BotEvent::ServerHeartbeat(ServerStatus {
+                name: "eu-1".to_string(),
+                track: "shutoko".to_string(),
+                players: vec![Player { name: "driver".to_string(), steamid: Some(76561197960287930) }],
+                capacity: 24,
+            }),
+            BotEvent::Ban {
+                id: 1, steamid: 76561197960287930, reason: "cheating".to_string(), scope: "all".to_string(),
+                expires_at: Some("2024-01-01T00:00:00Z".to_string()),
+            },
+            BotEvent::Unban { steamid: 76561197960287930, revoked: 1 },
+        ] {
+            assert_response(event);
+        }
+    }
+}
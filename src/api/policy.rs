@@ -0,0 +1,99 @@
+use serde::Deserialize;
+
+// A named whitelist policy, configured once in config.toml and referenced by name
+// from the game servers instead of every server sending its own list of role ids.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessPolicy {
+    pub name: String,
+    // The member needs at least one of these roles (ignored if empty)
+    #[serde(default)]
+    pub any_of: Vec<u64>,
+    // The member needs every one of these roles (ignored if empty)
+    #[serde(default)]
+    pub all_of: Vec<u64>,
+    // Members with any of these roles are always denied
+    #[serde(default)]
+    pub deny: Vec<u64>,
+    // Steamids which are always denied
+    #[serde(default)]
+    pub banned: Vec<i64>,
+    // Set for the legacy role list, which lets nobody through when it is empty
+    #[serde(skip)]
+    pub require_any: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub rule: String,
+}
+
+impl Decision {
+    fn allow(rule: String) -> Decision {
+        Decision { allowed: true, rule }
+    }
+
+    fn deny(rule: String) -> Decision {
+        Decision { allowed: false, rule }
+    }
+}
+
+impl AccessPolicy {
+    // Builds an anonymous policy from the raw role list older server plugins still send
+    pub fn from_roles(roles: &[i64]) -> AccessPolicy {
+        AccessPolicy {
+            name: "legacy".to_string(),
+            any_of: roles.iter().map(|role| *role as u64).collect(),
+            all_of: Vec::new(),
+            deny: Vec::new(),
+            banned: Vec::new(),
+            require_any: true,
+        }
+    }
+
+    pub fn is_banned(&self, steamid: i64) -> bool {
+        self.banned.contains(&steamid)
+    }
+
+    // Rules are checked in order: deny roles, all_of, any_of.
+    // A policy without any role rules lets every linked member through.
+    pub fn evaluate(&self, roles: &[u64]) -> Decision {
+        if let Some(role) = self.deny.iter().find(|role| roles.contains(role)) {
+            return Decision::deny(format!("deny:{}", role));
+        }
+
+        if let Some(role) = self.all_of.iter().find(|role| !roles.contains(role)) {
+            return Decision::deny(format!("missing:{}", role));
+        }
+
+        if !self.any_of.is_empty() || self.require_any {
+            return match self.any_of.iter().find(|role| roles.contains(role)) {
+                Some(role) => Decision::allow(format!("any_of:{}", role)),
+                None => Decision::deny("any_of".to_string()),
+            };
+        }
+
+        if !self.all_of.is_empty() {
+            return Decision::allow("all_of".to_string());
+        }
+
+        Decision::allow("linked".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_legacy_roles_deny() {
+        let decision = AccessPolicy::from_roles(&[]).evaluate(&[1234567890]);
+        assert_eq!(decision, Decision::deny("any_of".to_string()));
+    }
+
+    #[test]
+    fn legacy_roles_allow_any_of() {
+        let decision = AccessPolicy::from_roles(&[1234567890, 1234567891]).evaluate(&[1234567891]);
+        assert_eq!(decision, Decision::allow("any_of:1234567891".to_string()));
+    }
+}
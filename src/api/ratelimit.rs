@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use warp::reject::Reject;
+
+use crate::database::servers;
+use crate::database::Database;
+
+// Routes which can be limited, "default" applies to every route without its own limit
+pub const ROUTES: &[&str] = &["default", "checksteamid", "fetch_cutup_score", "insert_cutup_score", "update_driver_stats", "server_heartbeat", "ingest", "events", "public"];
+
+// Routes anyone may call. Their clients are told apart by IP only, a made up key would get a fresh bucket.
+pub const KEYLESS: &[&str] = &["public"];
+
+// Buckets untouched for this long are full again and can be forgotten
+const IDLE: Duration = Duration::from_secs(600);
+
+// Buckets kept at most, the least recently used one makes room for a new client
+const MAX_BUCKETS: usize = 10_000;
+
+// Keys of registered game servers are reloaded this often, a server registered since is limited by IP until then
+const SERVER_KEYS_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    // Requests allowed at once
+    pub burst: u32,
+    // Requests refilled per minute
+    pub per_minute: u32,
+}
+
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl Reject for RateLimited {}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Token buckets per route and client
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(&'static str, String), Bucket>>,
+    database: Arc<Database>,
+    // Ids of the registered game servers by the hash of their API key
+    server_keys: RwLock<Option<(Instant, Arc<HashMap<String, i32>>)>>,
+}
+
+impl RateLimiter {
+    pub fn new(database: Arc<Database>) -> RateLimiter {
+        RateLimiter {
+            buckets: Mutex::new(HashMap::new()),
+            database,
+            server_keys: RwLock::new(None),
+        }
+    }
+
+    // Who a request is counted against: a registered game server, a feed key, or else the client IP.
+    // A made up key doesn't get a bucket of its own.
+    pub async fn client(&self, key: Option<&str>, ip: Option<IpAddr>, feed_keys: &[String]) -> String {
+        if let Some(key) = key {
+            if feed_keys.iter().any(|feed_key| feed_key == key) {
+                return format!("feed:{}", super::key_fingerprint(key))
+            }
+            if let Some(id) = self.server_keys().await.get(&servers::hash_key(key)) {
+                return format!("server:{}", id)
+            }
+        }
+        ip.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+    }
+
+    // Reloaded on a timer only, so unknown keys don't cause a query each
+    async fn server_keys(&self) -> Arc<HashMap<String, i32>> {
+        if let Some((loaded, keys)) = &*self.server_keys.read().await {
+            if loaded.elapsed() < SERVER_KEYS_TTL {
+                return keys.clone()
+            }
+        }
+        let mut cached = self.server_keys.write().await;
+        if let Some((loaded, keys)) = &*cached {
+            if loaded.elapsed() < SERVER_KEYS_TTL {
+                return keys.clone()
+            }
+        }
+        let keys = match self.database.game_server_keys().await {
+            Ok(keys) => Arc::new(keys.into_iter().collect()),
+            Err(e) => {
+                log::error!("Failed to load the keys of the game servers: {}", e);
+                // Tried again after the TTL, the old keys stay in use until then
+                cached.as_ref().map(|(_, keys)| keys.clone()).unwrap_or_default()
+            },
+        };
+        *cached = Some((Instant::now(), keys.clone()));
+        keys
+    }
+
+    // Takes a token from the client's bucket, returns how long to wait if it is empty
+    pub fn check(&self, route: &'static str, client: &str, limit: &RateLimit) -> Result<(), Duration> {
+        let now = Instant::now();
+        let rate = limit.per_minute as f64 / 60.0;
+        let key = (route, client.to_string());
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated) < IDLE);
+            if buckets.len() >= MAX_BUCKETS {
+                let oldest = buckets.iter().min_by_key(|(_, bucket)| bucket.updated).map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    buckets.remove(&oldest);
+                }
+            }
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(limit.burst as f64);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if rate > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        } else {
+            Err(IDLE)
+        }
+    }
+}
\ No newline at end of file
@@ -1,48 +1,321 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
 use warp::Filter;
+use warp::filters::BoxedFilter;
+use warp::http::Method;
+use warp::reply::WithHeader;
 use super::models::AppState;
 use super::handlers;
+use super::openapi;
+use super::ratelimit::{self, RateLimited};
+use super::errors::{DatabaseError, ServerKeyRejected};
+use super::server::{MatchedRoute, RemoteAddr};
+use crate::metrics;
+use crate::database::servers::GameServer;
 
 use super::models;
 
+// A route of the API. The filters match requests against these paths, and the OpenAPI tests check that every one is
+// documented. A `{steamid}` segment matches a steamid.
+#[derive(Debug)]
+pub struct Route {
+    pub method: Method,
+    pub path: &'static str,
+}
+
+impl Route {
+    const fn get(path: &'static str) -> Route {
+        Route { method: Method::GET, path }
+    }
+
+    const fn post(path: &'static str) -> Route {
+        Route { method: Method::POST, path }
+    }
+
+    // Some with the steamid segment if there is one when the path is this route's
+    pub(super) fn matches(&self, path: &str) -> Option<Option<i64>> {
+        let mut steamid = None;
+        let mut segments = path.trim_start_matches('/').split('/');
+        for expected in self.path.trim_start_matches('/').split('/') {
+            let segment = segments.next()?;
+            if expected == "{steamid}" {
+                steamid = Some(segment.parse().ok()?);
+            } else if segment != expected {
+                return None
+            }
+        }
+        match segments.next() {
+            Some(_) => None,
+            None => Some(steamid),
+        }
+    }
+}
+
+pub static CHECK_STEAMID: Route = Route::post("/checksteamid");
+pub static HEARTBEAT: Route = Route::get("/heartbeat");
+pub static HEALTH_LIVE: Route = Route::get("/health/live");
+pub static HEALTH_READY: Route = Route::get("/health/ready");
+pub static METRICS: Route = Route::get("/metrics");
+pub static OPENAPI: Route = Route::get("/openapi.json");
+pub static DOCS: Route = Route::get("/docs");
+pub static DOCS_SCRIPT: Route = Route::get("/docs/swagger-ui-bundle.js");
+pub static DOCS_STYLE: Route = Route::get("/docs/swagger-ui.css");
+pub static FETCH_CUTUP_SCORE: Route = Route::post("/fetch_cutup_score");
+pub static INSERT_CUTUP_SCORE: Route = Route::post("/insert_cutup_score");
+pub static UPDATE_DRIVER_STATS: Route = Route::post("/update_driver_stats");
+pub static SERVER_HEARTBEAT: Route = Route::post("/server_heartbeat");
+pub static V1_CHECK_WHITELIST: Route = Route::post("/v1/whitelist/check");
+pub static V1_FETCH_CUTUP: Route = Route::get("/v1/players/{steamid}/cutup");
+pub static V1_SUBMIT_CUTUP: Route = Route::post("/v1/players/{steamid}/cutup");
+pub static V1_DRIVER_STATS: Route = Route::post("/v1/players/{steamid}/stats");
+pub static V1_SERVER_HEARTBEAT: Route = Route::post("/v1/servers/heartbeat");
+pub static V1_INGEST: Route = Route::post("/v1/ingest");
+pub static V1_EVENTS: Route = Route::get("/v1/events");
+pub static V1_PUBLIC_LEADERBOARD: Route = Route::get("/v1/public/leaderboards/cutup");
+pub static V1_PUBLIC_PROFILE: Route = Route::get("/v1/public/players/{steamid}");
+pub static V1_PUBLIC_TRACKS: Route = Route::get("/v1/public/tracks");
+pub static V1_PUBLIC_SEASONS: Route = Route::get("/v1/public/seasons");
+
+pub static PUBLIC_ROUTES: [&Route; 4] = [&V1_PUBLIC_LEADERBOARD, &V1_PUBLIC_PROFILE, &V1_PUBLIC_TRACKS, &V1_PUBLIC_SEASONS];
+
+pub static ROUTES: [&Route; 24] = [
+    &CHECK_STEAMID, &HEARTBEAT, &HEALTH_LIVE, &HEALTH_READY, &METRICS, &OPENAPI, &DOCS, &DOCS_SCRIPT, &DOCS_STYLE,
+    &FETCH_CUTUP_SCORE, &INSERT_CUTUP_SCORE, &UPDATE_DRIVER_STATS, &SERVER_HEARTBEAT,
+    &V1_CHECK_WHITELIST, &V1_FETCH_CUTUP, &V1_SUBMIT_CUTUP, &V1_DRIVER_STATS, &V1_SERVER_HEARTBEAT, &V1_INGEST, &V1_EVENTS,
+    &V1_PUBLIC_LEADERBOARD, &V1_PUBLIC_PROFILE, &V1_PUBLIC_TRACKS, &V1_PUBLIC_SEASONS,
+];
+
+// Matches the path of the route without consuming it, then its method. Once the path matched the route is recorded
+// for the request metrics, so requests it rejects are counted under it as well.
+pub(super) fn route(route: &'static Route) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    matching(route).map(|_: Option<i64>| ()).untuple_one()
+}
+
+// Like `route` for a route with a `{steamid}` segment, extracts the steamid
+pub(super) fn player_route(route: &'static Route) -> impl Filter<Extract = (i64,), Error = warp::Rejection> + Clone {
+    matching(route).and_then(|steamid: Option<i64>| async move { steamid.ok_or_else(warp::reject::not_found) })
+}
+
+fn matching(route: &'static Route) -> impl Filter<Extract = (Option<i64>,), Error = warp::Rejection> + Clone {
+    warp::path::full()
+        .and(warp::ext::optional::<MatchedRoute>())
+        .and_then(move |path: warp::path::FullPath, matched: Option<MatchedRoute>| async move {
+            let steamid = route.matches(path.as_str()).ok_or_else(warp::reject::not_found)?;
+            if let Some(matched) = matched {
+                matched.set(route.path);
+            }
+            Ok::<_, warp::Rejection>(steamid)
+        })
+        .and(method(&route.method))
+}
+
+// Boxed as get and post are different filter types, they reject with 405 when only the path matched
+fn method(method: &Method) -> BoxedFilter<()> {
+    if *method == Method::POST {
+        warp::post().boxed()
+    } else {
+        warp::get().boxed()
+    }
+}
+
 pub fn check_steamid_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("checksteamid")
-        .and(warp::post())
-        .and(warp::body::json::<models::CheckSteamid>())
+    route(&CHECK_STEAMID)
+        .and(legacy("checksteamid"))
+        .and(rate_limit("checksteamid", state.clone()))
+        .and(json_body::<models::CheckSteamid>(&state))
         .and(with_state(state))
-        .and_then(handlers::check_steamid)
+        .and_then(|data: models::CheckSteamid, state| with_steamid(data.steamid, handlers::check_steamid(data, state)))
+        .map(deprecated("/v1/whitelist/check"))
 }
 
 pub fn heartbeat_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("heartbeat")
-        .and(warp::get())
+    route(&HEARTBEAT)
         .and_then(handlers::heartbeat)
 }
 
+pub fn health_live_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&HEALTH_LIVE)
+        .and_then(handlers::health_live)
+}
+
+pub fn health_ready_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&HEALTH_READY)
+        .and(with_state(state))
+        .and_then(handlers::health_ready)
+}
+
+pub fn metrics_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&METRICS)
+        .and_then(handlers::prometheus_metrics)
+}
+
+pub fn openapi_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&OPENAPI)
+        .and_then(handlers::openapi)
+}
+
+pub fn docs_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&DOCS)
+        .and_then(handlers::docs)
+}
+
+pub fn docs_assets_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&DOCS_SCRIPT)
+        .map(|| ("text/javascript", openapi::DOCS_SCRIPT))
+        .or(route(&DOCS_STYLE).map(|| ("text/css", openapi::DOCS_STYLE)))
+        .unify()
+        .and_then(handlers::docs_asset)
+}
+
 pub fn cutup_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("fetch_cutup_score")
-        .and(warp::post())
-        .and(warp::body::json::<models::ScoreRequest>())
+    route(&FETCH_CUTUP_SCORE)
+        .and(legacy("fetch_cutup_score"))
+        .and(rate_limit("fetch_cutup_score", state.clone()))
+        .and(json_body::<models::ScoreRequest>(&state))
         .and(with_state(state))
-        .and_then(handlers::fetch_cutup_score)
+        .and_then(|data: models::ScoreRequest, state| with_steamid(data.steamid, handlers::fetch_cutup_score(data, state)))
+        .map(deprecated("/v1/players/{steamid}/cutup"))
 }
 
 pub fn insert_cutup_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("insert_cutup_score")
-        .and(warp::post())
-        .and(warp::body::json::<models::InsertScoreRequest>())
+    route(&INSERT_CUTUP_SCORE)
+        .and(legacy("insert_cutup_score"))
+        .and(rate_limit("insert_cutup_score", state.clone()))
+        .and(game_server(state.clone()))
+        .and(json_body::<models::InsertScoreRequest>(&state))
         .and(with_state(state))
-        .and_then(handlers::insert_cutup_score)
+        .and_then(|server: Option<GameServer>, data: models::InsertScoreRequest, state| with_steamid(data.steamid, handlers::insert_cutup_score(data, server, state)))
+        .map(deprecated("/v1/players/{steamid}/cutup"))
 }
 
 pub fn update_driver_stats_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path!("update_driver_stats")
-        .and(warp::post())
-        .and(warp::body::json::<models::UpdateDriverStatsRequest>())
+    route(&UPDATE_DRIVER_STATS)
+        .and(legacy("update_driver_stats"))
+        .and(rate_limit("update_driver_stats", state.clone()))
+        .and(game_server(state.clone()))
+        .and(json_body::<models::UpdateDriverStatsRequest>(&state))
+        .and(with_state(state))
+        .and_then(|server: Option<GameServer>, data: models::UpdateDriverStatsRequest, state| with_steamid(data.steamid, handlers::update_driver_stats(data, server, state)))
+        .map(deprecated("/v1/players/{steamid}/stats"))
+}
+
+pub fn server_heartbeat_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&SERVER_HEARTBEAT)
+        .and(legacy("server_heartbeat"))
+        .and(rate_limit("server_heartbeat", state.clone()))
+        .and(game_server(state.clone()))
+        .and(json_body::<models::ServerHeartbeat>(&state))
         .and(with_state(state))
-        .and_then(handlers::update_driver_stats)
+        .and_then(handlers::server_heartbeat)
+        .map(deprecated("/v1/servers/heartbeat"))
+}
+
+// Logs which API keys still use a legacy route, warns once per key and route for the first 1000 of them
+fn legacy(route: &'static str) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("x-api-key")
+        .map(move |key: Option<String>| {
+            metrics::LEGACY_REQUESTS.with_label_values(&[route]).inc();
+            let key = key.as_deref().map(super::key_fingerprint).unwrap_or_else(|| "none".to_string());
+            let first = {
+                let mut users = LEGACY_USERS.lock().unwrap();
+                // Arbitrary keys could grow the set forever, past the limit new clients are only logged at debug level
+                users.len() < MAX_LEGACY_USERS && users.insert((route, key.clone()))
+            };
+            if first {
+                log::warn!(api_key = key.as_str(); "Legacy route /{} used, clients should move to /v1", route);
+            } else {
+                log::debug!(api_key = key.as_str(); "Legacy route /{} used", route);
+            }
+        })
+        .untuple_one()
+}
+
+// Clients which already got their warning, per legacy route
+static LEGACY_USERS: Lazy<Mutex<HashSet<(&'static str, String)>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+const MAX_LEGACY_USERS: usize = 1000;
+
+// Marks the response of a legacy route as deprecated and points to its /v1 successor
+fn deprecated<R: warp::Reply>(successor: &'static str) -> impl Fn(R) -> WithHeader<WithHeader<R>> + Clone {
+    move |reply| {
+        let reply = warp::reply::with_header(reply, "deprecation", "true");
+        warp::reply::with_header(reply, "link", format!("<{}>; rel=\"successor-version\"", successor))
+    }
+}
+
+// Takes a token from the bucket of the requesting game server, feed key or IP, rejects with RateLimited if it is empty
+pub(super) fn rate_limit(route: &'static str, state: AppState) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("x-api-key")
+        .and(warp::ext::optional::<RemoteAddr>())
+        .and_then(move |key: Option<String>, remote: Option<RemoteAddr>| {
+            let state = state.clone();
+            async move {
+                let config = state.config.get();
+                let limit = match config.api.rate_limit(route) {
+                    Some(limit) => limit,
+                    None => return Ok(()),
+                };
+                let key = key.filter(|_| !ratelimit::KEYLESS.contains(&route));
+                let ip = remote.map(|RemoteAddr(address)| address.ip());
+                let client = state.rate_limiter.client(key.as_deref(), ip, &config.api.feed_keys).await;
+                state.rate_limiter.check(route, &client, limit).map_err(|retry_after| {
+                    log::warn!(client = client.as_str(); "Rate limited a client on {}, retry in {:.1}s", route, retry_after.as_secs_f64());
+                    warp::reject::custom(RateLimited { retry_after })
+                })
+            }
+        })
+        .untuple_one()
+}
+
+// The registered game server whose API key the write carries, None for anonymous writes
+pub(super) fn game_server(state: AppState) -> impl Filter<Extract = (Option<GameServer>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("x-api-key")
+        .and_then(move |key: Option<String>| {
+            let state = state.clone();
+            async move {
+                let server = match key {
+                    Some(key) => state.database.game_server_by_key(&key).await
+                        .map_err(|e| warp::reject::custom(DatabaseError::from(e)))?,
+                    None => None,
+                };
+                match server {
+                    Some(server) if !server.enabled => {
+                        log::warn!(server = server.name.as_str(); "Refused a write of a disabled game server");
+                        Err(warp::reject::custom(ServerKeyRejected::Disabled))
+                    },
+                    Some(server) => Ok(Some(server)),
+                    None => {
+                        // Otherwise a registered server could drop its key to get around its track list
+                        let require_server_key = state.config.get().api.require_server_key;
+                        let required = match require_server_key {
+                            Some(required) => required,
+                            None => state.database.has_game_servers().await
+                                .map_err(|e| warp::reject::custom(DatabaseError::from(e)))?,
+                        };
+                        if required {
+                            Err(warp::reject::custom(ServerKeyRejected::Unknown))
+                        } else {
+                            Ok(None)
+                        }
+                    },
+                }
+            }
+        })
+}
+
+// JSON body no bigger than `api.max_body_size`
+pub(super) fn json_body<T: DeserializeOwned + Send>(state: &AppState) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(state.config.get().api.max_body_size)
+        .and(warp::body::json::<T>())
+}
+
+// Adds the steamid a request is about to every line it logs
+pub(super) async fn with_steamid<F: std::future::Future>(steamid: i64, future: F) -> F::Output {
+    crate::logging::scope(vec![("steamid", steamid.to_string())], future).await
 }
 
-fn with_state(state: AppState) -> impl Filter<Extract = (AppState,), Error = warp::Rejection> + Clone {
+pub(super) fn with_state(state: AppState) -> impl Filter<Extract = (AppState,), Error = warp::Rejection> + Clone {
     warp::any()
         .and_then(move || {
             let cloned_state = state.clone();
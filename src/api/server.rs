@@ -0,0 +1,110 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn, Service};
+use hyper::{Body, Request, Response};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::server::TlsStream;
+use warp::http::HeaderValue;
+
+use super::models::AppState;
+use super::tls;
+use crate::logging;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Address of the client, read by routes through `warp::ext`
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub SocketAddr);
+
+// Path of the route which matched the request, set by the route filters and read for the request metrics
+#[derive(Debug, Clone, Default)]
+pub struct MatchedRoute(Arc<Mutex<Option<&'static str>>>);
+
+impl MatchedRoute {
+    pub fn set(&self, path: &'static str) {
+        *self.0.lock().unwrap() = Some(path);
+    }
+
+    fn get(&self) -> Option<&'static str> {
+        *self.0.lock().unwrap()
+    }
+}
+
+// Binds the API, the returned future serves it until `shutdown` resolves and the running requests finished.
+// Every request runs with its request id in the log context, taken from the x-request-id header or generated,
+// and is counted in the request metrics under the route which matched it.
+pub fn serve(state: AppState, address: SocketAddr, shutdown: impl Future<Output = ()> + Send + 'static) -> (SocketAddr, Pin<Box<dyn Future<Output = ()> + Send>>) {
+    let tls = state.config.get().api.tls.clone();
+    let service = warp::service(super::combined_routes(state));
+
+    match tls {
+        None => {
+            let make_service = make_service_fn(move |connection: &AddrStream| {
+                let service = service.clone();
+                let remote = RemoteAddr(connection.remote_addr());
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| handle(service.clone(), remote, request)))
+                }
+            });
+            let server = hyper::Server::bind(&address).serve(make_service);
+            let address = server.local_addr();
+            (address, Box::pin(async move {
+                if let Err(e) = server.with_graceful_shutdown(shutdown).await {
+                    log::error!("Web API failed: {}", e);
+                }
+            }))
+        },
+        Some(tls) => {
+            let acceptor = tls::Acceptor::new(tls).expect("Failed to set up TLS");
+            let listener = std::net::TcpListener::bind(address).expect("Failed to bind the web API");
+            listener.set_nonblocking(true).expect("Failed to bind the web API");
+            let listener = TcpListener::from_std(listener).expect("Failed to bind the web API");
+            let address = listener.local_addr().expect("Failed to bind the web API");
+
+            let make_service = make_service_fn(move |connection: &TlsStream<TcpStream>| {
+                let service = service.clone();
+                let remote = RemoteAddr(connection.get_ref().0.peer_addr().unwrap_or(address));
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| handle(service.clone(), remote, request)))
+                }
+            });
+            let server = hyper::Server::builder(acceptor.incoming(listener)).serve(make_service);
+            (address, Box::pin(async move {
+                if let Err(e) = server.with_graceful_shutdown(shutdown).await {
+                    log::error!("Web API failed: {}", e);
+                }
+            }))
+        },
+    }
+}
+
+fn request_id(request: &Request<Body>) -> String {
+    request.headers().get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+async fn handle<S>(mut service: S, remote: RemoteAddr, mut request: Request<Body>) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let request_id = request_id(&request);
+    let matched = MatchedRoute::default();
+    let started = Instant::now();
+    request.extensions_mut().insert(remote);
+    request.extensions_mut().insert(matched.clone());
+    let mut response = logging::scope(vec![("request_id", request_id.clone())], service.call(request)).await?;
+    super::record_request(matched.get(), response.status(), started.elapsed());
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(response)
+}
\ No newline at end of file
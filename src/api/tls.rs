@@ -0,0 +1,165 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use hyper::server::accept::Accept;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::{self, Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use super::config::TlsConfig;
+
+// Clients which don't finish the handshake in time are dropped, so they can't hold connections open
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn read_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file)).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates found in {}", path));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &str) -> Result<PrivateKey, String> {
+    let file = File::open(path).map_err(|e| format!("cannot open {}: {}", path, e))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file)).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    items.into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("no private key found in {}", path))
+}
+
+// Builds the rustls config from the files, also used to validate the config
+pub fn server_config(config: &TlsConfig) -> Result<ServerConfig, String> {
+    let certs = read_certs(&config.cert_path)?;
+    let key = read_key(&config.key_path)?;
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.client_ca_path {
+        // Mutual TLS, game servers have to present a certificate signed by this CA
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in read_certs(path)? {
+                roots.add(&cert).map_err(|e| format!("invalid CA certificate in {}: {}", path, e))?;
+            }
+            builder.with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(roots).boxed())
+        },
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder.with_single_cert(certs, key).map_err(|e| format!("invalid certificate or key: {}", e))?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+// Hands out the current rustls config, which is replaced when the files change
+pub struct Acceptor {
+    config: TlsConfig,
+    current: RwLock<Arc<ServerConfig>>,
+    modified: RwLock<Vec<Option<SystemTime>>>,
+}
+
+impl Acceptor {
+    pub fn new(config: TlsConfig) -> Result<Arc<Acceptor>, String> {
+        let server_config = server_config(&config)?;
+        let acceptor = Arc::new(Acceptor {
+            modified: RwLock::new(modified(&config)),
+            current: RwLock::new(Arc::new(server_config)),
+            config,
+        });
+        if acceptor.config.reload_interval > 0 {
+            tokio::spawn(reload_loop(Arc::downgrade(&acceptor)));
+        }
+        Ok(acceptor)
+    }
+
+    fn reload(&self) {
+        let modified = modified(&self.config);
+        if *self.modified.read().unwrap() == modified {
+            return;
+        }
+        // Files are often replaced one by one, a half written pair fails here and is picked up next time
+        match server_config(&self.config) {
+            Ok(server_config) => {
+                *self.current.write().unwrap() = Arc::new(server_config);
+                *self.modified.write().unwrap() = modified;
+                log::info!("Reloaded TLS certificate from {}", self.config.cert_path);
+            },
+            Err(e) => log::error!("Failed to reload TLS certificate: {}", e),
+        }
+    }
+
+    // Accepts connections and does the handshakes, the stream ends when the server stops listening
+    pub fn incoming(self: Arc<Self>, listener: TcpListener) -> Incoming {
+        let (sender, receiver) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote) = tokio::select! {
+                    _ = sender.closed() => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            log::error!("Failed to accept connection: {}", e);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            continue;
+                        }
+                    },
+                };
+                let acceptor = TlsAcceptor::from(self.current.read().unwrap().clone());
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send(stream).await;
+                        },
+                        Ok(Err(e)) => log::debug!("TLS handshake with {} failed: {}", remote, e),
+                        Err(_) => log::debug!("TLS handshake with {} timed out", remote),
+                    }
+                });
+            }
+        });
+        Incoming { receiver }
+    }
+}
+
+fn modified(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    [Some(&config.cert_path), Some(&config.key_path), config.client_ca_path.as_ref()].iter()
+        .flatten()
+        .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .collect()
+}
+
+// Checks the files for changes until the acceptor is dropped
+async fn reload_loop(acceptor: Weak<Acceptor>) {
+    loop {
+        let interval = match acceptor.upgrade() {
+            Some(acceptor) => Duration::from_secs(acceptor.config.reload_interval),
+            None => return,
+        };
+        tokio::time::sleep(interval).await;
+        match acceptor.upgrade() {
+            Some(acceptor) => acceptor.reload(),
+            None => return,
+        }
+    }
+}
+
+// Connections which finished the TLS handshake, served by hyper
+pub struct Incoming {
+    receiver: mpsc::Receiver<TlsStream<TcpStream>>,
+}
+
+impl Accept for Incoming {
+    type Conn = TlsStream<TcpStream>;
+    type Error = std::io::Error;
+
+    fn poll_accept(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.receiver.poll_recv(cx).map(|stream| stream.map(Ok))
+    }
+}
\ No newline at end of file
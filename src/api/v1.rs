@@ -0,0 +1,163 @@
+use warp::Filter;
+use super::models::{self, AppState};
+use super::handlers;
+use crate::database::servers::GameServer;
+use super::routes::*;
+
+// The versioned API. Handlers are shared with the legacy routes, which also share their rate limits.
+pub fn routes(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    check_whitelist_route(state.clone())
+        .or(fetch_cutup_route(state.clone()))
+        .or(submit_cutup_route(state.clone()))
+        .or(driver_stats_route(state.clone()))
+        .or(server_heartbeat_route(state.clone()))
+        .or(ingest_route(state.clone()))
+        .or(events_route(state.clone()))
+        .or(public_routes(state))
+}
+
+pub fn check_whitelist_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&V1_CHECK_WHITELIST)
+        .and(rate_limit("checksteamid", state.clone()))
+        .and(json_body::<models::CheckSteamid>(&state))
+        .and(with_state(state))
+        .and_then(|data: models::CheckSteamid, state| with_steamid(data.steamid, handlers::check_steamid(data, state)))
+}
+
+pub fn fetch_cutup_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    player_route(&V1_FETCH_CUTUP)
+        .and(rate_limit("fetch_cutup_score", state.clone()))
+        .and(warp::query::<models::CutupQuery>())
+        .and(with_state(state))
+        .and_then(|steamid: i64, query: models::CutupQuery, state| {
+            let data = models::ScoreRequest { steamid, track: query.track, car: query.car };
+            with_steamid(steamid, handlers::fetch_cutup_score(data, state))
+        })
+}
+
+pub fn submit_cutup_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    player_route(&V1_SUBMIT_CUTUP)
+        .and(rate_limit("insert_cutup_score", state.clone()))
+        .and(game_server(state.clone()))
+        .and(json_body::<models::CutupSubmission>(&state))
+        .and(with_state(state))
+        .and_then(|steamid: i64, server: Option<GameServer>, body: models::CutupSubmission, state| {
+            let data = models::InsertScoreRequest { steamid, track: body.track, car: body.car, score: body.score, event_id: body.event_id };
+            with_steamid(steamid, handlers::insert_cutup_score(data, server, state))
+        })
+}
+
+pub fn driver_stats_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    player_route(&V1_DRIVER_STATS)
+        .and(rate_limit("update_driver_stats", state.clone()))
+        .and(game_server(state.clone()))
+        .and(json_body::<models::DriverStatsSubmission>(&state))
+        .and(with_state(state))
+        .and_then(|steamid: i64, server: Option<GameServer>, body: models::DriverStatsSubmission, state| {
+            let data = models::UpdateDriverStatsRequest {
+                steamid,
+                track: body.track,
+                time: body.time,
+                avgspeed: body.avgspeed,
+                collisions: body.collisions,
+                distance: body.distance,
+                event_id: body.event_id,
+            };
+            with_steamid(steamid, handlers::update_driver_stats(data, server, state))
+        })
+}
+
+pub fn server_heartbeat_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&V1_SERVER_HEARTBEAT)
+        .and(rate_limit("server_heartbeat", state.clone()))
+        .and(game_server(state.clone()))
+        .and(json_body::<models::ServerHeartbeat>(&state))
+        .and(with_state(state))
+        .and_then(handlers::server_heartbeat)
+}
+
+// Retried batches carry the same Idempotency-Key header and get the stored results back
+pub fn ingest_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&V1_INGEST)
+        .and(rate_limit("ingest", state.clone()))
+        .and(game_server(state.clone()))
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(warp::body::content_length_limit(state.config.get().api.max_ingest_body_size))
+        .and(warp::body::json::<models::IngestRequest>())
+        .and(with_state(state))
+        .and_then(handlers::ingest)
+}
+
+pub fn events_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&V1_EVENTS)
+        .and(rate_limit("events", state.clone()))
+        .and(warp::query::<models::EventsQuery>())
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and(with_state(state))
+        .and_then(handlers::events)
+}
+
+// The public routes are read by the website from the browser. CORS wraps them only once their path matched,
+// otherwise it would answer preflights and refuse requests with an Origin header on every other path.
+pub fn public_routes(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    public_path().and(
+        public_leaderboard_route(state.clone())
+            .or(public_profile_route(state.clone()))
+            .or(public_tracks_route(state.clone()))
+            .or(public_seasons_route(state))
+            .with(public_cors())
+    )
+}
+
+// Matches the paths of the public routes without consuming them
+fn public_path() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::path::full()
+        .and_then(|path: warp::path::FullPath| async move {
+            match PUBLIC_ROUTES.iter().any(|route| route.matches(path.as_str()).is_some()) {
+                true => Ok(()),
+                false => Err(warp::reject::not_found()),
+            }
+        })
+        .untuple_one()
+}
+
+fn public_cors() -> warp::cors::Builder {
+    warp::cors()
+        .allow_any_origin()
+        .allow_methods(vec!["GET"])
+        .allow_headers(vec!["if-none-match"])
+        .expose_headers(vec!["etag"])
+}
+
+pub fn public_leaderboard_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&V1_PUBLIC_LEADERBOARD)
+        .and(rate_limit("public", state.clone()))
+        .and(warp::query::<models::LeaderboardQuery>())
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(with_state(state))
+        .and_then(handlers::public_leaderboard)
+}
+
+pub fn public_profile_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    player_route(&V1_PUBLIC_PROFILE)
+        .and(rate_limit("public", state.clone()))
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(with_state(state))
+        .and_then(|steamid: i64, if_none_match: Option<String>, state: AppState| with_steamid(steamid, handlers::public_profile(steamid, if_none_match, state)))
+}
+
+pub fn public_tracks_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&V1_PUBLIC_TRACKS)
+        .and(rate_limit("public", state.clone()))
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(with_state(state))
+        .and_then(handlers::public_tracks)
+}
+
+pub fn public_seasons_route(state: AppState) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    route(&V1_PUBLIC_SEASONS)
+        .and(rate_limit("public", state.clone()))
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(with_state(state))
+        .and_then(handlers::public_seasons)
+}
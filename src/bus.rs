@@ -0,0 +1,134 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::database::bans::Ban;
+use crate::database::scores::Highscore;
+use crate::status::ServerStatus;
+
+// Topics of the /v1/events feed, every event belongs to one of them
+pub const TOPICS: &[&str] = &["scores", "leaderboards", "links", "servers", "bans"];
+
+// Events slower subscribers may fall behind by before they skip ahead
+const CAPACITY: usize = 1024;
+
+// Things happening in the bot which outside consumers like overlays want to know about
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotEvent {
+    // A cutup score which improved the personal best of a driver
+    Highscore {
+        steamid: i64,
+        track: String,
+        car: String,
+        score: i64,
+        previous: Option<i64>,
+        rank: i64,
+        server_record: bool,
+    },
+    // The entries of a leaderboard changed since the last update
+    Leaderboard {
+        title: String,
+        entries: Vec<LeaderboardEntry>,
+    },
+    // A discord user linked their steamid
+    Link {
+        discordid: i64,
+        steamid: i64,
+    },
+    ServerHeartbeat(ServerStatus),
+    Ban {
+        id: i32,
+        steamid: i64,
+        reason: String,
+        scope: String,
+        // RFC 3339, permanent if not set
+        expires_at: Option<String>,
+    },
+    // All active bans of the driver were lifted
+    Unban {
+        steamid: i64,
+        revoked: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LeaderboardEntry {
+    pub discordid: i64,
+    pub score: i64,
+}
+
+impl BotEvent {
+    pub fn topic(&self) -> &'static str {
+        match self {
+            BotEvent::Highscore { .. } => "scores",
+            BotEvent::Leaderboard { .. } => "leaderboards",
+            BotEvent::Link { .. } => "links",
+            BotEvent::ServerHeartbeat(_) => "servers",
+            BotEvent::Ban { .. } | BotEvent::Unban { .. } => "bans",
+        }
+    }
+
+    // The `type` of the event, as used in webhook filters
+    pub fn name(&self) -> &'static str {
+        match self {
+            BotEvent::Highscore { .. } => "highscore",
+            BotEvent::Leaderboard { .. } => "leaderboard",
+            BotEvent::Link { .. } => "link",
+            BotEvent::ServerHeartbeat(_) => "server_heartbeat",
+            BotEvent::Ban { .. } => "ban",
+            BotEvent::Unban { .. } => "unban",
+        }
+    }
+}
+
+impl From<&Highscore> for BotEvent {
+    fn from(highscore: &Highscore) -> BotEvent {
+        BotEvent::Highscore {
+            steamid: highscore.steamid,
+            track: highscore.track.clone(),
+            car: highscore.car.clone(),
+            score: highscore.score,
+            previous: highscore.previous,
+            rank: highscore.rank,
+            server_record: highscore.is_server_record(),
+        }
+    }
+}
+
+impl From<&Ban> for BotEvent {
+    fn from(ban: &Ban) -> BotEvent {
+        BotEvent::Ban {
+            id: ban.id,
+            steamid: ban.steamid,
+            reason: ban.reason.clone(),
+            scope: ban.scope.clone(),
+            expires_at: ban.expires_at.map(|expires_at| humantime::format_rfc3339_seconds(expires_at).to_string()),
+        }
+    }
+}
+
+// Broadcast bus the handlers publish to. Events are dropped if nobody listens, nothing is persisted.
+#[derive(Debug)]
+pub struct EventBus {
+    sender: broadcast::Sender<BotEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: BotEvent) {
+        // Only fails without subscribers
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BotEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
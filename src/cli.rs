@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::PathBuf;
+use clap::{Parser, Subcommand, Args, ValueEnum};
+use poise::serenity_prelude as serenity;
+
+use crate::{Config, Error};
+use crate::database::Database;
+
+#[derive(Debug, Parser)]
+#[command(name = "swimbotrs", about = "swim> discord bot and game server API")]
+pub struct Cli {
+    /// Path of the config file
+    #[arg(short, long, global = true, default_value = "config.toml")]
+    pub config: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the bot and the API (default)
+    Run(RunArgs),
+    /// Check the config file and exit
+    CheckConfig,
+    /// Create or update the database tables
+    Migrate,
+    /// Register the slash commands with discord
+    RegisterCommands {
+        /// Register globally instead of in the configured guild
+        #[arg(long)]
+        global: bool,
+    },
+    /// Export a table as JSON
+    Export {
+        #[arg(value_enum)]
+        table: ExportTable,
+        /// File to write to, stdout if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Default, Args)]
+pub struct RunArgs {
+    /// Only run the web API, without connecting to the discord gateway
+    #[arg(long, conflicts_with = "bot_only")]
+    pub api_only: bool,
+    /// Only run the discord bot, without the web API
+    #[arg(long)]
+    pub bot_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportTable {
+    Steamids,
+    Cutup,
+    CutupHistory,
+    DriverStats,
+    Leaderboards,
+    Bans,
+    Laps,
+    Servers,
+}
+
+impl ExportTable {
+    fn table(&self) -> &'static str {
+        match self {
+            ExportTable::Steamids => "steamids",
+            ExportTable::Cutup => "cutup",
+            ExportTable::CutupHistory => "cutup_history",
+            ExportTable::DriverStats => "driver_stats",
+            ExportTable::Leaderboards => "leaderboards",
+            ExportTable::Bans => "bans",
+            ExportTable::Laps => "lap",
+            ExportTable::Servers => "servers",
+        }
+    }
+}
+
+pub async fn migrate(config: &Config) -> Result<(), Error> {
+    let database = Database::new(config.database.clone()).await?;
+    let applied = database.migrate().await?;
+    if applied.is_empty() {
+        println!("Database is up to date");
+    }
+    for name in applied {
+        println!("Applied migration {}", name);
+    }
+    Ok(())
+}
+
+pub async fn register_commands(config: &Config, global: bool) -> Result<(), Error> {
+    let http = serenity::Http::new(&config.discord.token);
+    let commands = crate::discord::commands::all();
+    if global {
+        poise::builtins::register_globally(&http, &commands).await?;
+        println!("Registered {} commands globally", commands.len());
+    } else {
+        poise::builtins::register_in_guild(&http, &commands, serenity::GuildId::new(config.discord.guild)).await?;
+        println!("Registered {} commands in guild {}", commands.len(), config.discord.guild);
+    }
+    Ok(())
+}
+
+pub async fn export(config: &Config, table: ExportTable, output: Option<PathBuf>) -> Result<(), Error> {
+    let database = Database::new(config.database.clone()).await?;
+    // The table name comes from ExportTable, never from user input
+    let row = database.query_one(
+        &format!("SELECT COALESCE(json_agg(t), '[]'::json) FROM {} t", table.table()),
+        &[],
+    ).await?;
+    let rows: serde_json::Value = row.get(0);
+    let json = serde_json::to_string_pretty(&rows)?;
+    match output {
+        Some(path) => {
+            fs::write(&path, json)?;
+            println!("Exported {} to {}", table.table(), path.display());
+        },
+        None => println!("{}", json),
+    }
+    Ok(())
+}
@@ -1,27 +1,428 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::io;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 use serde::Deserialize;
 use log::LevelFilter;
+use toml::{Table, Value};
 
-// Custom deserializer function
-fn deserialize_level_filter<'de, D>(deserializer: D) -> Result<LevelFilter, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    match s.to_lowercase().as_str() {
-        "off" => Ok(LevelFilter::Off),
-        "error" => Ok(LevelFilter::Error),
-        "warn" => Ok(LevelFilter::Warn),
-        "info" => Ok(LevelFilter::Info),
-        "debug" => Ok(LevelFilter::Debug),
-        "trace" => Ok(LevelFilter::Trace),
-        _ => Ok(LevelFilter::Info),
-    } 
+use crate::Config;
+use crate::logging::LogFormat;
+
+const ENV_PREFIX: &str = "SWIMBOT_";
+const FILE_SUFFIX: &str = "_file";
+
+// Every table of the config, used to tell table names apart from field names with underscores
+const TABLES: &[&str] = &["log", "log.modules", "discord", "discord.announcements", "api", "api.rate_limits", "api.tls", "api.public", "database", "status", "webhooks"];
+
+// String fields which can be set through the environment. Their values are never parsed, so a numeric password
+// or token stays a string even if the config file doesn't set the field. `.*` covers every key of a table.
+const STRING_FIELDS: &[&str] = &[
+    "log.level", "log.file_output", "log.modules.*", "discord.token", "discord.prefix", "api.address",
+    "api.tls.cert_path", "api.tls.key_path", "api.tls.client_ca_path", "database.address", "database.username",
+    "database.password", "database.database", "status.presence_format", "api.feed_keys",
+];
+
+// Array fields which can be set through the environment, as a TOML array or a comma separated list like
+// `SWIMBOT_API_FEED_KEYS=abc,def`. Items of string fields stay strings, the others are parsed one by one.
+const ARRAY_FIELDS: &[&str] = &["api.feed_keys", "discord.owners"];
+
+// Loads the config file and applies overrides, from lowest to highest precedence:
+// 1. `field = value` in the config file
+// 2. `field_file = "/path"` in the config file, the field is set to the contents of the file
+// 3. `SWIMBOT_SECTION_FIELD_FILE=/path` environment variables
+// 4. `SWIMBOT_SECTION_FIELD=value` environment variables
+// The config file may be missing if everything is set through the environment.
+pub fn load(path: &Path) -> Result<Config, String> {
+    let mut table: Table = if path.exists() {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Unable to load data from {} `{}`", path.display(), e))?
+    } else {
+        eprintln!("Config file {} not found, using environment variables only", path.display());
+        Table::new()
+    };
+
+    resolve_secret_files(&mut table)?;
+
+    let vars: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+        .collect();
+    apply_env(&mut table, &vars)?;
+
+    Value::Table(table).try_into()
+        .map_err(|e| format!("Unable to load data from {} `{}`", path.display(), e))
+}
+
+// Applies the `SWIMBOT_` variables, the `_FILE` ones first so the plain ones win
+fn apply_env(table: &mut Table, vars: &[(String, String)]) -> Result<(), String> {
+    let suffix = FILE_SUFFIX.to_uppercase();
+    for (name, file) in vars.iter().filter(|(name, _)| name.ends_with(&suffix)) {
+        let name = &name[..name.len() - suffix.len()];
+        let contents = read_secret(file).map_err(|e| format!("{}{}: {}", name, suffix, e))?;
+        apply_override(table, name, &contents)?;
+    }
+    for (name, value) in vars.iter().filter(|(name, _)| !name.ends_with(&suffix)) {
+        apply_override(table, name, value)?;
+    }
+    Ok(())
+}
+
+fn read_secret(path: &str) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map(|contents| contents.trim_end_matches(&['\r', '\n'][..]).to_string())
+        .map_err(|e| format!("Failed to read secret file {}: {}", path, e))
+}
+
+// Replaces every `field_file = "/path"` with `field = "<contents of /path>"`
+fn resolve_secret_files(table: &mut Table) -> Result<(), String> {
+    let keys: Vec<String> = table.keys().cloned().collect();
+    for key in keys {
+        if let Some(Value::Table(inner)) = table.get_mut(&key) {
+            resolve_secret_files(inner)?;
+            continue;
+        }
+        if !key.ends_with(FILE_SUFFIX) {
+            continue;
+        }
+        if let Some(Value::String(path)) = table.remove(&key) {
+            let contents = read_secret(&path).map_err(|e| format!("{}: {}", key, e))?;
+            table.insert(key[..key.len() - FILE_SUFFIX.len()].to_string(), Value::String(contents));
+        }
+    }
+    Ok(())
+}
+
+// Sets the field named by an environment variable, e.g. SWIMBOT_DISCORD_TOKEN sets discord.token
+fn apply_override(table: &mut Table, name: &str, raw: &str) -> Result<(), String> {
+    let segments: Vec<String> = name[ENV_PREFIX.len()..]
+        .split('_')
+        .map(|segment| segment.to_lowercase())
+        .collect();
+
+    let mut current = table;
+    let mut path = String::new();
+    let mut rest = &segments[..];
+    // Descend into the longest matching table until only the field name is left
+    'descend: while rest.len() > 1 {
+        for len in (1..rest.len()).rev() {
+            let key = rest[..len].join("_");
+            let full = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+            let is_table = matches!(current.get(&key), Some(Value::Table(_))) || TABLES.contains(&full.as_str());
+            if is_table {
+                current = match current.entry(key).or_insert_with(|| Value::Table(Table::new())) {
+                    Value::Table(inner) => inner,
+                    _ => return Ok(()),
+                };
+                path = full;
+                rest = &rest[len..];
+                continue 'descend;
+            }
+        }
+        break;
+    }
+
+    let key = rest.join("_");
+    let full = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+    let is_string = STRING_FIELDS.contains(&full.as_str())
+        || STRING_FIELDS.contains(&format!("{}.*", path).as_str())
+        || matches!(current.get(&key), Some(Value::String(_)));
+    let value = if ARRAY_FIELDS.contains(&full.as_str()) {
+        parse_array(raw, is_string).map_err(|e| format!("{}: {}", name, e))?
+    } else if is_string {
+        // Keep strings as they are, e.g. a numeric password
+        Value::String(raw.to_string())
+    } else {
+        parse_value(raw)
+    };
+    current.insert(key, value);
+    Ok(())
+}
+
+// A TOML array, or a comma separated list whose items are strings or parsed like single values
+fn parse_array(raw: &str, strings: bool) -> Result<Value, String> {
+    if raw.trim_start().starts_with('[') {
+        return match parse_value(raw) {
+            Value::Array(items) => Ok(Value::Array(items)),
+            _ => Err(format!("`{}` is not a valid TOML array", raw)),
+        }
+    }
+    Ok(Value::Array(raw.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| if strings { Value::String(item.to_string()) } else { parse_value(item) })
+        .collect()))
+}
+
+// Parses a TOML literal (number, bool, array...), anything else is taken as a string
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+// Config handle shared by the bot and the API which can be swapped at runtime.
+// Readers take a snapshot with `get` and keep using it for the task at hand.
+#[derive(Debug)]
+pub struct SharedConfig {
+    path: PathBuf,
+    current: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+    pub fn new(path: PathBuf, config: Config) -> SharedConfig {
+        SharedConfig {
+            path,
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    pub fn get(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    // Loads and validates the config file again and swaps it in.
+    // Returns the changed fields which only take effect after a restart.
+    pub fn reload(&self) -> Result<Vec<&'static str>, String> {
+        let config = load(&self.path)?;
+        let issues = validate(&config);
+        if !issues.is_empty() {
+            let issues: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+            return Err(format!("Invalid config: {}", issues.join(", ")));
+        }
+
+        let restart_required = restart_required(&self.get(), &config);
+        crate::logging::set_levels(&config.log);
+        *self.current.write().unwrap() = Arc::new(config);
+        log::info!("Reloaded config from {}", self.path.display());
+        if !restart_required.is_empty() {
+            log::warn!("Changed fields which need a restart: {}", restart_required.join(", "));
+        }
+        Ok(restart_required)
+    }
+}
+
+// Fields read once at startup, everything else is read on use and applies live
+fn restart_required(old: &Config, new: &Config) -> Vec<&'static str> {
+    let checks = [
+        ("log.file_output", old.log.file_output != new.log.file_output),
+        ("log.stdout", old.log.stdout != new.log.stdout),
+        ("log.format", old.log.format != new.log.format),
+        ("log rotation", (old.log.max_size_mb, old.log.rotate_interval, old.log.keep) != (new.log.max_size_mb, new.log.rotate_interval, new.log.keep)),
+        ("discord.token", old.discord.token != new.discord.token),
+        ("discord.prefix", old.discord.prefix != new.discord.prefix),
+        ("discord.guild", old.discord.guild != new.discord.guild),
+        ("api.address", old.api.address != new.api.address),
+        ("api.port", old.api.port != new.api.port),
+        ("api.heartbeat_expiry", old.api.heartbeat_expiry != new.api.heartbeat_expiry),
+        ("api.max_body_size", old.api.max_body_size != new.api.max_body_size),
+        ("api.max_ingest_body_size", old.api.max_ingest_body_size != new.api.max_ingest_body_size),
+        ("api.tls", old.api.tls != new.api.tls),
+        ("database", old.database != new.database),
+    ];
+    checks.iter().filter(|(_, changed)| *changed).map(|(field, _)| *field).collect()
+}
+
+pub fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+pub fn parse_level_filter(level: &str) -> Option<LevelFilter> {
+    match level.to_lowercase().as_str() {
+        "off" => Some(LevelFilter::Off),
+        "error" => Some(LevelFilter::Error),
+        "warn" => Some(LevelFilter::Warn),
+        "info" => Some(LevelFilter::Info),
+        "debug" => Some(LevelFilter::Debug),
+        "trace" => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+// A problem found while validating the config, `path` names the field like `discord.guild`
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+// Collects issues, every config section reports into the same list
+#[derive(Debug, Default)]
+pub struct Issues(pub Vec<ConfigIssue>);
+
+impl Issues {
+    pub fn push(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ConfigIssue { path: path.into(), message: message.into() });
+    }
+
+    pub fn check(&mut self, ok: bool, path: impl Into<String>, message: impl Into<String>) {
+        if !ok {
+            self.push(path, message);
+        }
+    }
+
+    // Discord ids are never 0, an unset optional id has to be left out instead
+    pub fn check_id(&mut self, id: u64, path: impl Into<String>) {
+        self.check(id != 0, path, "must be a discord id, not 0");
+    }
+}
+
+// Checks the whole config and reports every problem at once
+pub fn validate(config: &Config) -> Vec<ConfigIssue> {
+    let mut issues = Issues::default();
+    issues.check(config.shutdown_timeout > 0, "shutdown_timeout", "must be at least 1 second");
+    config.log.validate(&mut issues);
+    config.discord.validate(&mut issues);
+    config.api.validate(&mut issues);
+    config.database.validate(&mut issues);
+    config.status.validate(&mut issues);
+    config.webhooks.validate(&mut issues);
+    issues.0
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Log {
-    #[serde(deserialize_with = "deserialize_level_filter")]
-    pub level: LevelFilter,
+    pub level: String,
     pub file_output: String,
     pub stdout: bool,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub format: LogFormat,
+    // Rotate the log file once it is bigger than this, 0 to disable
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+    // Seconds after which the log file is rotated, 0 to disable
+    #[serde(default)]
+    pub rotate_interval: u64,
+    // Number of rotated files to keep
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    // Level overrides by module path, e.g. `serenity = "warn"`
+    #[serde(default)]
+    pub modules: HashMap<String, String>,
+}
+
+fn default_max_size_mb() -> u64 {
+    100
+}
+
+fn default_keep() -> usize {
+    5
+}
+
+impl Log {
+    // Falls back to info, typos are reported by `validate`
+    pub fn level_filter(&self) -> LevelFilter {
+        parse_level_filter(&self.level).unwrap_or(LevelFilter::Info)
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check(
+            parse_level_filter(&self.level).is_some(),
+            "log.level",
+            format!("unknown level \"{}\", use one of off, error, warn, info, debug, trace", self.level),
+        );
+        for (module, level) in &self.modules {
+            issues.check(
+                parse_level_filter(level).is_some(),
+                format!("log.modules.{}", module),
+                format!("unknown level \"{}\", use one of off, error, warn, info, debug, trace", level),
+            );
+        }
+        if self.file_output.is_empty() {
+            issues.push("log.file_output", "must not be empty");
+        } else if let Err(e) = check_writable(Path::new(&self.file_output)) {
+            issues.push("log.file_output", format!("cannot open {} for writing: {}", self.file_output, e));
+        }
+    }
+}
+
+// Checks this process can write the file without creating it, check-config must not leave an empty log file behind.
+// Permission bits alone don't tell, e.g. a directory owned by another user, so a probe file is created and removed.
+fn check_writable(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        return OpenOptions::new().append(true).open(path).map(|_| ())
+    }
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let probe = parent.join(format!(".{}.probe-{}", name, std::process::id()));
+    OpenOptions::new().write(true).create_new(true).open(&probe)?;
+    fs::remove_file(&probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn numeric_env_password_stays_a_string() {
+        let mut table = Table::new();
+        apply_override(&mut table, "SWIMBOT_DATABASE_PASSWORD", "123456").unwrap();
+        apply_override(&mut table, "SWIMBOT_DATABASE_PORT", "5432").unwrap();
+        assert_eq!(table["database"]["password"], Value::String("123456".to_string()));
+        assert_eq!(table["database"]["port"], Value::Integer(5432));
+    }
+
+    #[test]
+    fn env_sets_nested_fields_with_underscores() {
+        let mut table = Table::new();
+        apply_override(&mut table, "SWIMBOT_API_MAX_BODY_SIZE", "4096").unwrap();
+        assert_eq!(table["api"]["max_body_size"], Value::Integer(4096));
+    }
+
+    #[test]
+    fn env_sets_top_level_fields_with_underscores() {
+        let mut table = Table::new();
+        apply_override(&mut table, "SWIMBOT_SHUTDOWN_TIMEOUT", "30").unwrap();
+        assert_eq!(table["shutdown_timeout"], Value::Integer(30));
+        assert!(!table.contains_key("shutdown"));
+    }
+
+    #[test]
+    fn env_secret_file_is_read_and_loses_to_the_plain_variable() {
+        let path = env::temp_dir().join(format!("swimbot-secret-{}", std::process::id()));
+        fs::write(&path, "hunter2\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+
+        let mut table = Table::new();
+        apply_env(&mut table, &vars(&[("SWIMBOT_DATABASE_PASSWORD_FILE", path.as_str())])).unwrap();
+        assert_eq!(table["database"]["password"], Value::String("hunter2".to_string()));
+
+        let mut table = Table::new();
+        apply_env(&mut table, &vars(&[("SWIMBOT_DISCORD_TOKEN", "plain"), ("SWIMBOT_DISCORD_TOKEN_FILE", path.as_str())])).unwrap();
+        assert_eq!(table["discord"]["token"], Value::String("plain".to_string()));
+
+        fs::remove_file(&path).unwrap();
+        assert!(apply_env(&mut Table::new(), &vars(&[("SWIMBOT_DATABASE_PASSWORD_FILE", path.as_str())])).is_err());
+    }
+
+    #[test]
+    fn env_array_fields_take_lists() {
+        let mut table = Table::new();
+        apply_override(&mut table, "SWIMBOT_API_FEED_KEYS", "abc").unwrap();
+        assert_eq!(table["api"]["feed_keys"], Value::Array(vec![Value::String("abc".to_string())]));
+        apply_override(&mut table, "SWIMBOT_API_FEED_KEYS", "abc, 123").unwrap();
+        assert_eq!(table["api"]["feed_keys"], Value::Array(vec![Value::String("abc".to_string()), Value::String("123".to_string())]));
+        apply_override(&mut table, "SWIMBOT_API_FEED_KEYS", r#"["abc", "def"]"#).unwrap();
+        assert_eq!(table["api"]["feed_keys"], Value::Array(vec![Value::String("abc".to_string()), Value::String("def".to_string())]));
+        apply_override(&mut table, "SWIMBOT_DISCORD_OWNERS", "1,2").unwrap();
+        assert_eq!(table["discord"]["owners"], Value::Array(vec![Value::Integer(1), Value::Integer(2)]));
+
+        let error = apply_override(&mut table, "SWIMBOT_API_FEED_KEYS", r#"["abc""#).unwrap_err();
+        assert!(error.starts_with("SWIMBOT_API_FEED_KEYS"), "{}", error);
+    }
+}
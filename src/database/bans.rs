@@ -0,0 +1,78 @@
+use std::time::SystemTime;
+use tokio_postgres::{Error, Row};
+
+use super::Database;
+
+// A ban either blocks a driver everywhere or only from joining servers / submitting scores
+pub const SCOPES: [&str; 3] = ["all", "servers", "scores"];
+
+#[derive(Debug, Clone)]
+pub struct Ban {
+    pub id: i32,
+    pub steamid: i64,
+    pub reason: String,
+    pub issuer: i64,
+    pub scope: String,
+    pub created_at: SystemTime,
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Ban {
+    fn from_row(row: &Row) -> Ban {
+        Ban {
+            id: row.get("id"),
+            steamid: row.get("steamid"),
+            reason: row.get("reason"),
+            issuer: row.get("issuer"),
+            scope: row.get("scope"),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+        }
+    }
+}
+
+impl Database {
+    // Returns the newest active ban of the steamid which applies to the given scope
+    pub async fn active_ban(&self, steamid: i64, scope: &str) -> Result<Option<Ban>, Error> {
+        let rows = self.query(
+            "SELECT id, steamid, reason, issuer, scope, created_at, expires_at FROM bans
+            WHERE steamid = $1 AND NOT revoked
+            AND (expires_at IS NULL OR expires_at > NOW())
+            AND (scope = 'all' OR scope = $2)
+            ORDER BY created_at DESC LIMIT 1",
+            &[&steamid, &scope],
+        ).await?;
+        Ok(rows.first().map(Ban::from_row))
+    }
+
+    pub async fn active_bans(&self) -> Result<Vec<Ban>, Error> {
+        let rows = self.query(
+            "SELECT id, steamid, reason, issuer, scope, created_at, expires_at FROM bans
+            WHERE NOT revoked AND (expires_at IS NULL OR expires_at > NOW())
+            ORDER BY created_at DESC",
+            &[],
+        ).await?;
+        Ok(rows.iter().map(Ban::from_row).collect())
+    }
+
+    pub async fn insert_ban(&self, steamid: i64, reason: &str, issuer: i64, scope: &str, expires_at: Option<SystemTime>) -> Result<Ban, Error> {
+        let row = self.query_one(
+            "INSERT INTO bans (steamid, reason, issuer, scope, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, steamid, reason, issuer, scope, created_at, expires_at",
+            &[&steamid, &reason, &issuer, &scope, &expires_at],
+        ).await?;
+        Ok(Ban::from_row(&row))
+    }
+
+    // Revokes every active ban of the steamid, returns how many were revoked
+    pub async fn revoke_bans(&self, steamid: i64) -> Result<usize, Error> {
+        let rows = self.query(
+            "UPDATE bans SET revoked = TRUE
+            WHERE steamid = $1 AND NOT revoked AND (expires_at IS NULL OR expires_at > NOW())
+            RETURNING id",
+            &[&steamid],
+        ).await?;
+        Ok(rows.len())
+    }
+}
\ No newline at end of file
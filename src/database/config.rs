@@ -1,10 +1,21 @@
 use serde::Deserialize;
 
-#[derive(Debug, Clone, Deserialize)]
+use crate::config::Issues;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct DatabaseConfig {
     pub address: String,
     pub port: u16,
     pub username: String,
     pub password: String,
     pub database: String,
+}
+
+impl DatabaseConfig {
+    pub fn validate(&self, issues: &mut Issues) {
+        issues.check(!self.address.is_empty(), "database.address", "must not be empty");
+        issues.check(self.port != 0, "database.port", "must not be 0");
+        issues.check(!self.username.is_empty(), "database.username", "must not be empty");
+        issues.check(!self.database.is_empty(), "database.database", "must not be empty");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::time::sleep;
+use tokio_postgres::types::Json;
+use tokio_postgres::{Error, GenericClient};
+
+use super::Database;
+use crate::config::SharedConfig;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::shutdown::Shutdown;
+
+// Submissions may carry an event id, the result of the first one is stored under it so retries of the
+// same submission get that result back instead of being applied again. Event ids are scoped per game server
+// (0 for anonymous submissions), so one server can't pick up the result of another's event.
+// Claiming, applying and finishing run in one transaction, a failed submission leaves no claim behind and a
+// concurrent claim of the same event waits for the commit, so a claim is never seen without its result.
+#[derive(Debug)]
+pub enum Claim {
+    // Not seen within the TTL, the caller applies the event and stores the result with `finish`
+    New,
+    // Already applied, with the stored result
+    Done(Value),
+}
+
+pub async fn claim<C: GenericClient + Sync>(client: &C, scope: i32, event_id: &str, ttl: u64) -> Result<Claim, Error> {
+    // Expired events are taken over, as are rows without a result which older versions left behind on a crash
+    let claimed = client.execute(
+        "INSERT INTO processed_events (server_id, event_id) VALUES ($1, $2)
+        ON CONFLICT (server_id, event_id) DO UPDATE SET result = NULL, created_at = NOW()
+        WHERE processed_events.created_at < NOW() - make_interval(secs => $3) OR processed_events.result IS NULL",
+        &[&scope, &event_id, &(ttl as f64)],
+    ).await?;
+    if claimed > 0 {
+        return Ok(Claim::New)
+    }
+
+    let row = client.query_one("SELECT result FROM processed_events WHERE server_id = $1 AND event_id = $2", &[&scope, &event_id]).await?;
+    let Json(result) = row.get::<usize, Json<Value>>(0);
+    Ok(Claim::Done(result))
+}
+
+pub async fn finish<C: GenericClient + Sync>(client: &C, scope: i32, event_id: &str, result: &Value) -> Result<(), Error> {
+    client.execute(
+        "UPDATE processed_events SET result = $3 WHERE server_id = $1 AND event_id = $2",
+        &[&scope, &event_id, &Json(result)],
+    ).await?;
+    Ok(())
+}
+
+impl Database {
+    // Removes events older than the TTL, returns how many were removed
+    pub async fn prune_events(&self, ttl: u64) -> Result<u64, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["prune_events"]).start_timer();
+        self.client.execute(
+            "DELETE FROM processed_events WHERE created_at < NOW() - make_interval(secs => $1)",
+            &[&(ttl as f64)],
+        ).await
+    }
+}
+// Removes expired event ids once an hour, the TTL is read every time so config reloads apply
+pub async fn prune_loop(database: Arc<Database>, config: Arc<SharedConfig>, mut shutdown: Shutdown) {
+    loop {
+        match database.prune_events(config.get().api.dedup_ttl).await {
+            Ok(0) => {},
+            Ok(pruned) => log::debug!("Pruned {} processed events", pruned),
+            Err(e) => log::error!("Failed to prune processed events: {}", e),
+        }
+        tokio::select! {
+            _ = sleep(Duration::from_secs(60 * 60)) => {},
+            _ = shutdown.wait() => break,
+        }
+    }
+}
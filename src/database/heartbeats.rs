@@ -0,0 +1,43 @@
+use std::time::Duration;
+use tokio_postgres::types::Json;
+use tokio_postgres::Error;
+
+use super::Database;
+use crate::metrics::DB_QUERY_DURATION;
+use crate::status::ServerStatus;
+
+// Heartbeats are only stored when the API and the bot run in separate processes, so the bot's
+// status panel and presence see the servers the API heard from.
+impl Database {
+    pub async fn save_heartbeat(&self, status: &ServerStatus) -> Result<(), Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["save_heartbeat"]).start_timer();
+        self.client.execute(
+            "INSERT INTO server_heartbeats (name, status) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET status = EXCLUDED.status, seen_at = NOW()",
+            &[&status.name, &Json(status)],
+        ).await?;
+        Ok(())
+    }
+
+    // Heartbeats received within `expiry` seconds, with how long ago they were received
+    pub async fn recent_heartbeats(&self, expiry: u64) -> Result<Vec<(ServerStatus, Duration)>, Error> {
+        let rows = self.query(
+            "SELECT status, EXTRACT(EPOCH FROM NOW() - seen_at)::FLOAT8 FROM server_heartbeats
+            WHERE seen_at > NOW() - make_interval(secs => $1)",
+            &[&(expiry as f64)],
+        ).await?;
+        Ok(rows.iter().map(|row| {
+            let Json(status): Json<ServerStatus> = row.get(0);
+            (status, Duration::from_secs_f64(row.get::<usize, f64>(1).max(0.0)))
+        }).collect())
+    }
+
+    // Removes heartbeats older than `expiry` seconds, servers which were renamed or shut down would stay forever
+    pub async fn prune_heartbeats(&self, expiry: u64) -> Result<u64, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["prune_heartbeats"]).start_timer();
+        self.client.execute(
+            "DELETE FROM server_heartbeats WHERE seen_at < NOW() - make_interval(secs => $1)",
+            &[&(expiry as f64)],
+        ).await
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_postgres::types::Json;
+use tokio_postgres::{Error, GenericClient};
+
+use super::Database;
+use super::events::{self, Claim};
+use super::scores::{self, Highscore};
+use crate::metrics::{self, DB_QUERY_DURATION};
+
+// Idempotency keys are remembered this long, plugins have to retry buffered batches within it
+const BATCH_RETENTION: &str = "7 days";
+
+// One entry of a batch sent to /v1/ingest
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IngestEvent {
+    CutupScore {
+        steamid: i64,
+        track: String,
+        car: String,
+        score: i64,
+        event_id: Option<String>,
+    },
+    DriverStats {
+        steamid: i64,
+        track: String,
+        time: i64,
+        avgspeed: i64,
+        collisions: i64,
+        distance: f64,
+        event_id: Option<String>,
+    },
+    Lap {
+        steamid: i64,
+        track: String,
+        car: String,
+        time: i64,
+        event_id: Option<String>,
+    },
+}
+
+impl IngestEvent {
+    pub fn steamid(&self) -> i64 {
+        match self {
+            IngestEvent::CutupScore { steamid, .. } | IngestEvent::DriverStats { steamid, .. } | IngestEvent::Lap { steamid, .. } => *steamid,
+        }
+    }
+
+    pub fn track(&self) -> &str {
+        match self {
+            IngestEvent::CutupScore { track, .. } | IngestEvent::DriverStats { track, .. } | IngestEvent::Lap { track, .. } => track,
+        }
+    }
+
+    pub fn event_id(&self) -> Option<&str> {
+        match self {
+            IngestEvent::CutupScore { event_id, .. } | IngestEvent::DriverStats { event_id, .. } | IngestEvent::Lap { event_id, .. } => event_id.as_deref(),
+        }
+    }
+
+    // Applies the event unless its event id was processed before, then the stored result is returned.
+    // Has to run in a transaction, so the claim is only kept if the event was applied.
+    async fn apply_once<C: GenericClient + Sync>(&self, client: &C, index: usize, dedup_ttl: u64, server_id: Option<i32>) -> Result<Applied, Error> {
+        let event_id = match self.event_id() {
+            Some(event_id) => event_id,
+            None => {
+                let (status, highscore) = self.apply(client, server_id).await?;
+                return Ok(Applied::new(IngestResult { index, status: status.to_string(), message: None }, highscore))
+            },
+        };
+
+        // Anonymous submissions share scope 0
+        let scope = server_id.unwrap_or(0);
+        match events::claim(client, scope, event_id, dedup_ttl).await? {
+            Claim::New => {},
+            Claim::Done(result) => {
+                metrics::DUPLICATE_EVENTS.inc();
+                // Results stored by older versions of the single event routes are upper case
+                let status = result["status"].as_str().unwrap_or("duplicate").to_lowercase();
+                let message = result["message"].as_str().map(|message| message.to_string());
+                return Ok(Applied { result: IngestResult { index, status, message }, highscore: None, duplicate: true })
+            },
+        }
+        let (status, highscore) = self.apply(client, server_id).await?;
+        events::finish(client, scope, event_id, &json!({ "status": status, "message": null })).await?;
+        Ok(Applied::new(IngestResult { index, status: status.to_string(), message: None }, highscore))
+    }
+
+    // Returns the status of the event and the highscore if it was an improved cutup score
+    async fn apply<C: GenericClient + Sync>(&self, client: &C, server_id: Option<i32>) -> Result<(&'static str, Option<Highscore>), Error> {
+        match self {
+            IngestEvent::CutupScore { steamid, track, car, score, .. } => {
+                let highscore = scores::insert_cutup_score(client, *steamid, track, car, *score, server_id).await?;
+                Ok((if highscore.is_some() { "improved" } else { "not_improved" }, highscore))
+            },
+            IngestEvent::DriverStats { steamid, track, time, avgspeed, collisions, distance, .. } => {
+                scores::add_driver_stats(client, *steamid, track, *time, *avgspeed, *collisions, *distance, server_id).await?;
+                Ok(("ok", None))
+            },
+            IngestEvent::Lap { steamid, track, car, time, .. } => {
+                let improved = scores::insert_lap(client, *steamid, track, car, *time, server_id).await?;
+                Ok((if improved { "improved" } else { "not_improved" }, None))
+            },
+        }
+    }
+}
+
+// Outcome of one event, `index` is its position in the batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestResult {
+    pub index: usize,
+    pub status: String,
+    pub message: Option<String>,
+}
+
+impl IngestResult {
+    // Errors are applied again when the batch is retried
+    fn is_final(&self) -> bool {
+        self.status != "error"
+    }
+}
+
+// Outcome of one event and the highscore if it improved a cutup score
+#[derive(Debug)]
+pub struct Applied {
+    pub result: IngestResult,
+    pub highscore: Option<Highscore>,
+    // The event id was processed before, `result` is the stored one
+    pub duplicate: bool,
+}
+
+impl Applied {
+    fn new(result: IngestResult, highscore: Option<Highscore>) -> Applied {
+        Applied { result, highscore, duplicate: false }
+    }
+}
+
+#[derive(Debug)]
+pub struct Ingested {
+    pub results: Vec<IngestResult>,
+    // Improved cutup scores of the events applied now, so replayed ones aren't announced twice
+    pub highscores: Vec<Highscore>,
+    // Indexes of the events applied now, all of them for a new batch and the unfinished ones for a retry
+    pub applied: Vec<usize>,
+    // The key was seen before, events which had reached a final status kept their stored result
+    pub replayed: bool,
+}
+
+impl Database {
+    // Applies a single submission in its own transaction, the counterpart of a batch with one event
+    pub async fn apply_event(&self, event: &IngestEvent, dedup_ttl: u64, server_id: Option<i32>) -> Result<Applied, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["apply_event"]).start_timer();
+        let mut client = self.transactions.lock().await;
+        let transaction = client.transaction().await?;
+        let applied = event.apply_once(&transaction, 0, dedup_ttl, server_id).await?;
+        transaction.commit().await?;
+        Ok(applied)
+    }
+
+    // Runs the events in one transaction. Every event gets a savepoint, so a failing one doesn't take
+    // the others with it. `rejected` are the results of events refused before, they are stored with
+    // the rest so a retry under the same key gets the same answer. Rows are tagged with `server_id`,
+    // which also scopes the idempotency key so two servers can't pick up each other's batches.
+    pub async fn ingest(&self, key: Option<&str>, mut events: Vec<(usize, IngestEvent)>, mut rejected: Vec<IngestResult>, dedup_ttl: u64, server_id: Option<i32>) -> Result<Ingested, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["ingest"]).start_timer();
+        let mut client = self.transactions.lock().await;
+        let mut transaction = client.transaction().await?;
+        // Anonymous batches share scope 0
+        let scope = server_id.unwrap_or(0);
+
+        // Batches run one at a time on this connection, so a concurrent retry finds the stored results here
+        let mut results = Vec::new();
+        let mut replayed = false;
+        if let Some(key) = key {
+            if let Some(row) = transaction.query("SELECT results FROM ingest_batches WHERE server_id = $1 AND key = $2", &[&scope, &key]).await?.first() {
+                let Json(stored) = row.get::<usize, Json<Vec<IngestResult>>>(0);
+                replayed = true;
+                // Only the events which didn't reach a final status run again, the rest keep their stored result
+                let unfinished: Vec<usize> = stored.iter().filter(|result| !result.is_final()).map(|result| result.index).collect();
+                events.retain(|(index, _)| unfinished.contains(index));
+                rejected.retain(|result| unfinished.contains(&result.index));
+                let retried: Vec<usize> = events.iter().map(|(index, _)| *index).chain(rejected.iter().map(|result| result.index)).collect();
+                results = stored.into_iter().filter(|result| !retried.contains(&result.index)).collect();
+            }
+        }
+
+        results.extend(rejected);
+        let applied = events.iter().map(|(index, _)| *index).collect();
+        let mut highscores = Vec::new();
+        for (index, event) in events {
+            let savepoint = transaction.savepoint("event").await?;
+            match event.apply_once(&savepoint, index, dedup_ttl, server_id).await {
+                Ok(outcome) => {
+                    savepoint.commit().await?;
+                    results.push(outcome.result);
+                    highscores.extend(outcome.highscore);
+                },
+                Err(e) => {
+                    savepoint.rollback().await?;
+                    log::error!("Failed to ingest event {} ({:?}): {}", index, event, e);
+                    results.push(IngestResult { index, status: "error".to_string(), message: Some("Failed to store the event".to_string()) });
+                },
+            }
+        }
+        results.sort_by_key(|result| result.index);
+
+        if let Some(key) = key {
+            transaction.execute(
+                "INSERT INTO ingest_batches (server_id, key, results) VALUES ($1, $2, $3)
+                ON CONFLICT (server_id, key) DO UPDATE SET results = EXCLUDED.results",
+                &[&scope, &key, &Json(&results)],
+            ).await?;
+        }
+        transaction.execute(
+            &*format!("DELETE FROM ingest_batches WHERE created_at < NOW() - INTERVAL '{}'", BATCH_RETENTION),
+            &[],
+        ).await?;
+        transaction.commit().await?;
+
+        Ok(Ingested { results, highscores, applied, replayed })
+    }
+}
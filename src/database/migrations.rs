@@ -0,0 +1,180 @@
+use tokio_postgres::Error;
+
+use super::Database;
+
+// Every migration runs once and in order, applied migrations are tracked in schema_migrations.
+// Never edit a migration which was released, add a new one instead.
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("0001_base", "
+        CREATE TABLE IF NOT EXISTS steamids (
+            discordid BIGINT UNIQUE,
+            steamid BIGINT
+        );
+        CREATE TABLE IF NOT EXISTS cutup (
+            steamid BIGINT NOT NULL,
+            track TEXT NOT NULL,
+            car TEXT NOT NULL,
+            score BIGINT NOT NULL,
+            UNIQUE (steamid, track, car)
+        );
+        CREATE TABLE IF NOT EXISTS driver_stats (
+            steamid BIGINT NOT NULL,
+            track TEXT NOT NULL,
+            collisions BIGINT NOT NULL DEFAULT 0,
+            distance DOUBLE PRECISION NOT NULL DEFAULT 0,
+            avgspeed BIGINT NOT NULL DEFAULT 0,
+            total_time BIGINT NOT NULL DEFAULT 0,
+            UNIQUE (steamid, track)
+        );
+        CREATE TABLE IF NOT EXISTS leaderboards (
+            title TEXT NOT NULL,
+            channel BIGINT NOT NULL,
+            query TEXT NOT NULL,
+            discord_role BIGINT
+        );
+    "),
+    ("0002_bans", "
+        CREATE TABLE IF NOT EXISTS bans (
+            id SERIAL PRIMARY KEY,
+            steamid BIGINT NOT NULL,
+            reason TEXT NOT NULL,
+            issuer BIGINT NOT NULL,
+            scope TEXT NOT NULL DEFAULT 'all',
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            expires_at TIMESTAMPTZ,
+            revoked BOOLEAN NOT NULL DEFAULT FALSE
+        );
+        CREATE INDEX IF NOT EXISTS bans_steamid ON bans (steamid);
+    "),
+    ("0003_notification_settings", "
+        CREATE TABLE IF NOT EXISTS notification_settings (
+            discordid BIGINT PRIMARY KEY,
+            enabled BOOLEAN NOT NULL DEFAULT FALSE,
+            boards TEXT[],
+            quiet_start SMALLINT,
+            quiet_end SMALLINT
+        );
+    "),
+    ("0004_lap", "
+        CREATE TABLE IF NOT EXISTS lap (
+            steamid BIGINT NOT NULL,
+            map TEXT NOT NULL,
+            car TEXT NOT NULL,
+            score BIGINT NOT NULL
+        );
+        CREATE UNIQUE INDEX IF NOT EXISTS lap_steamid_map_car ON lap (steamid, map, car);
+    "),
+    ("0005_ingest_batches", "
+        CREATE TABLE IF NOT EXISTS ingest_batches (
+            key TEXT PRIMARY KEY,
+            results JSONB NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+        CREATE INDEX IF NOT EXISTS ingest_batches_created_at ON ingest_batches (created_at);
+    "),
+    ("0006_processed_events", "
+        CREATE TABLE IF NOT EXISTS processed_events (
+            event_id TEXT PRIMARY KEY,
+            result JSONB,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+        CREATE INDEX IF NOT EXISTS processed_events_created_at ON processed_events (created_at);
+    "),
+    ("0007_public_api", "
+        ALTER TABLE steamids ADD COLUMN IF NOT EXISTS public_profile BOOLEAN NOT NULL DEFAULT FALSE;
+        CREATE TABLE IF NOT EXISTS cutup_history (
+            steamid BIGINT NOT NULL,
+            track TEXT NOT NULL,
+            car TEXT NOT NULL,
+            score BIGINT NOT NULL,
+            set_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+        CREATE INDEX IF NOT EXISTS cutup_history_set_at ON cutup_history (set_at);
+        CREATE INDEX IF NOT EXISTS cutup_track_score ON cutup (track, score DESC);
+    "),
+    ("0008_webhooks", "
+        CREATE TABLE IF NOT EXISTS webhooks (
+            id SERIAL PRIMARY KEY,
+            url TEXT NOT NULL,
+            events TEXT[] NOT NULL,
+            secret TEXT NOT NULL,
+            created_by BIGINT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+        CREATE TABLE IF NOT EXISTS webhook_dead_letters (
+            id SERIAL PRIMARY KEY,
+            webhook_id INTEGER NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+            event TEXT NOT NULL,
+            payload JSONB NOT NULL,
+            attempts INTEGER NOT NULL,
+            error TEXT NOT NULL,
+            failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+        CREATE INDEX IF NOT EXISTS webhook_dead_letters_failed_at ON webhook_dead_letters (failed_at);
+    "),
+    ("0009_servers", "
+        CREATE TABLE IF NOT EXISTS servers (
+            id SERIAL PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            region TEXT NOT NULL,
+            api_key_hash TEXT NOT NULL UNIQUE,
+            allowed_tracks TEXT[] NOT NULL DEFAULT '{}',
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+        ALTER TABLE cutup ADD COLUMN IF NOT EXISTS server_id INTEGER REFERENCES servers (id) ON DELETE SET NULL;
+        ALTER TABLE cutup_history ADD COLUMN IF NOT EXISTS server_id INTEGER REFERENCES servers (id) ON DELETE SET NULL;
+        ALTER TABLE driver_stats ADD COLUMN IF NOT EXISTS server_id INTEGER REFERENCES servers (id) ON DELETE SET NULL;
+        ALTER TABLE lap ADD COLUMN IF NOT EXISTS server_id INTEGER REFERENCES servers (id) ON DELETE SET NULL;
+    "),
+    ("0010_profile_names", "
+        ALTER TABLE steamids ADD COLUMN IF NOT EXISTS display_name TEXT;
+    "),
+    ("0011_ingest_batch_scope", "
+        ALTER TABLE ingest_batches ADD COLUMN IF NOT EXISTS server_id INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE ingest_batches DROP CONSTRAINT IF EXISTS ingest_batches_pkey;
+        ALTER TABLE ingest_batches ADD PRIMARY KEY (server_id, key);
+    "),
+    ("0012_server_heartbeats", "
+        CREATE TABLE IF NOT EXISTS server_heartbeats (
+            name TEXT PRIMARY KEY,
+            status JSONB NOT NULL,
+            seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+    "),
+    ("0013_processed_event_scope", "
+        ALTER TABLE processed_events ADD COLUMN IF NOT EXISTS server_id INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE processed_events DROP CONSTRAINT IF EXISTS processed_events_pkey;
+        ALTER TABLE processed_events ADD PRIMARY KEY (server_id, event_id);
+    "),
+];
+
+impl Database {
+    // Applies all pending migrations, returns the names of the applied ones
+    pub async fn migrate(&self) -> Result<Vec<&'static str>, Error> {
+        self.batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                name TEXT PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )",
+        ).await?;
+        let applied: Vec<String> = self.query("SELECT name FROM schema_migrations", &[]).await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let mut newly_applied = Vec::new();
+        for (name, sql) in MIGRATIONS {
+            if applied.iter().any(|applied| applied == name) {
+                continue;
+            }
+            log::info!("Applying migration {}", name);
+            self.batch_execute(&format!(
+                "BEGIN; {} INSERT INTO schema_migrations (name) VALUES ('{}'); COMMIT;",
+                sql, name,
+            )).await?;
+            newly_applied.push(*name);
+        }
+        Ok(newly_applied)
+    }
+}
\ No newline at end of file
@@ -1,41 +1,94 @@
 pub mod config;
+pub mod bans;
+pub mod scores;
+pub mod migrations;
+pub mod ingest;
+pub mod events;
+pub mod rankings;
+pub mod webhooks;
+pub mod servers;
+pub mod heartbeats;
 
+use tokio::sync::{watch, Mutex};
 use tokio_postgres::{NoTls, Error, Client};
 
+use crate::metrics::DB_QUERY_DURATION;
+
 #[derive(Debug)]
 pub struct Database {
     client: Client,
+    // Dedicated connection for transactions, the shared client can't hold one open while serving other queries.
+    // The lock is the only thing serializing the writes of the API, e.g. concurrent scores on one track.
+    transactions: Mutex<Client>,
+    // Set once a connection task finished, which happens after its client was dropped
+    closed: Vec<watch::Receiver<bool>>,
+}
+
+async fn connect(config: &config::DatabaseConfig) -> Result<(Client, watch::Receiver<bool>), Error> {
+    let (client, connection) = tokio_postgres::connect(
+        &format!("host={} user={} password={} dbname={} port={}",
+            config.address, config.username, config.password, config.database, config.port),
+        NoTls,
+    ).await?;
+
+    let (closed_sender, closed) = watch::channel(false);
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("Error occured while accessing the database: \n {}", e);
+        }
+        let _ = closed_sender.send(true);
+    });
+    Ok((client, closed))
 }
 
 impl Database {
     pub async fn new(config: config::DatabaseConfig) -> Result<Database, Error> {
-        let (client, connection) = tokio_postgres::connect(
-            &format!("host={} user={} password={} dbname={} port={}",
-                config.address, config.username, config.password, config.database, config.port),
-            NoTls,
-        ).await?;
-
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("Error occured while accessing the database: \n {}", e);
-            }
-        });
+        let (client, client_closed) = connect(&config).await?;
+        let (transactions, transactions_closed) = connect(&config).await?;
 
-        Ok(Database { client })
+        Ok(Database {
+            client,
+            transactions: Mutex::new(transactions),
+            closed: vec![client_closed, transactions_closed],
+        })
+    }
+
+    // Resolves once the connections are closed. Take this before dropping the last
+    // reference to the database to wait for the connections to shut down cleanly.
+    pub fn closed(&self) -> impl std::future::Future<Output = ()> {
+        let connections = self.closed.clone();
+        async move {
+            for mut closed in connections {
+                while !*closed.borrow() {
+                    if closed.changed().await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
     }
 
     pub async fn query_one(&self, query: &str, params: &[&(dyn tokio_postgres::types::ToSql + Sync)]) -> Result<tokio_postgres::Row, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["query_one"]).start_timer();
         let row = self.client.query_one(query, params).await?;
         Ok(row)
     }
 
     pub async fn query(&self, query: &str, params: &[&(dyn tokio_postgres::types::ToSql + Sync)]) -> Result<Vec<tokio_postgres::Row>, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["query"]).start_timer();
         let rows = self.client.query(query, params).await?;
         Ok(rows)
     }
 
     pub async fn execute(&self, query: &str, params: &[&(dyn tokio_postgres::types::ToSql + Sync)]) -> Result<(), Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["execute"]).start_timer();
         self.client.execute(query, params).await?;
         Ok(())
     }
+
+    // Runs multiple statements without parameters
+    pub async fn batch_execute(&self, query: &str) -> Result<(), Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["batch_execute"]).start_timer();
+        self.client.batch_execute(query).await
+    }
 }
\ No newline at end of file
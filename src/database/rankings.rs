@@ -0,0 +1,194 @@
+use std::time::SystemTime;
+use serde::Serialize;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Error;
+
+use super::Database;
+use crate::metrics::DB_QUERY_DURATION;
+
+// Best score per driver, track and car of all time
+const ALL_TIME: &str = "SELECT steamid, track, car, score FROM cutup";
+// Best score per driver, track and car set between $3 and $4
+const PERIOD: &str = "SELECT steamid, track, car, MAX(score) AS score FROM cutup_history
+    WHERE set_at >= $3 AND set_at < $4 GROUP BY steamid, track, car";
+
+// Which cutup scores a leaderboard ranks, unset filters match everything
+#[derive(Debug, Clone, Default)]
+pub struct RankingFilter {
+    pub track: Option<String>,
+    pub car: Option<String>,
+    // Start and end of a season
+    pub period: Option<(SystemTime, SystemTime)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RankedScore {
+    pub rank: i64,
+    pub steamid: i64,
+    // Discord display name, only filled in for members with a public profile
+    pub name: Option<String>,
+    #[serde(skip)]
+    pub discordid: Option<i64>,
+    pub track: String,
+    pub car: String,
+    pub score: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerProfile {
+    pub steamid: i64,
+    pub name: Option<String>,
+    #[serde(skip)]
+    pub discordid: Option<i64>,
+    pub scores: Vec<ProfileScore>,
+    pub laps: Vec<ProfileLap>,
+    pub stats: Vec<TrackStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileScore {
+    pub track: String,
+    pub car: String,
+    pub score: i64,
+    // Position on the leaderboard of the track and car
+    pub rank: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileLap {
+    pub track: String,
+    pub car: String,
+    pub time: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackStats {
+    pub track: String,
+    pub time: i64,
+    pub avgspeed: i64,
+    pub collisions: i64,
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Track {
+    pub name: String,
+    pub cars: Vec<String>,
+}
+
+impl Database {
+    // Returns one page of the cutup leaderboard and the number of entries on all pages
+    pub async fn cutup_rankings(&self, filter: &RankingFilter, limit: i64, offset: i64) -> Result<(Vec<RankedScore>, i64), Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["cutup_rankings"]).start_timer();
+        // Parameters $1 and $2 are the track and car, the season adds $3 and $4
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&filter.track, &filter.car];
+        let source = match &filter.period {
+            Some((start, end)) => {
+                params.push(start);
+                params.push(end);
+                PERIOD
+            },
+            None => ALL_TIME,
+        };
+        let conditions = "($1::TEXT IS NULL OR track = $1) AND ($2::TEXT IS NULL OR car = $2)";
+
+        let total: i64 = self.client.query_one(&*format!(
+            "WITH scores AS ({}) SELECT COUNT(*) FROM scores WHERE {}",
+            source, conditions,
+        ), &params).await?.get(0);
+
+        let page = format!("LIMIT ${} OFFSET ${}", params.len() + 1, params.len() + 2);
+        params.push(&limit);
+        params.push(&offset);
+        let rows = self.client.query(&*format!(
+            "WITH scores AS ({})
+            SELECT ranked.rank, ranked.steamid, ranked.track, ranked.car, ranked.score, profile.discordid, profile.display_name
+            FROM (
+                SELECT RANK() OVER (ORDER BY score DESC) AS rank, steamid, track, car, score
+                FROM scores WHERE {}
+            ) ranked
+            LEFT JOIN LATERAL (
+                SELECT discordid, display_name FROM steamids WHERE steamids.steamid = ranked.steamid AND public_profile LIMIT 1
+            ) profile ON TRUE
+            ORDER BY ranked.rank, ranked.steamid {}",
+            source, conditions, page,
+        ), &params).await?;
+        let scores = rows.iter().map(|row| RankedScore {
+            rank: row.get("rank"),
+            steamid: row.get("steamid"),
+            name: row.get("display_name"),
+            discordid: row.get("discordid"),
+            track: row.get("track"),
+            car: row.get("car"),
+            score: row.get("score"),
+        }).collect();
+
+        Ok((scores, total))
+    }
+
+    // Returns None if nothing is known about the steamid
+    pub async fn player_profile(&self, steamid: i64) -> Result<Option<PlayerProfile>, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["player_profile"]).start_timer();
+        let scores: Vec<ProfileScore> = self.client.query(
+            "SELECT track, car, score,
+                (SELECT COUNT(*) FROM cutup other WHERE other.track = cutup.track AND other.car = cutup.car AND other.score > cutup.score) + 1 AS rank
+            FROM cutup WHERE steamid = $1 ORDER BY track, car",
+            &[&steamid],
+        ).await?.iter().map(|row| ProfileScore {
+            track: row.get("track"),
+            car: row.get("car"),
+            score: row.get("score"),
+            rank: row.get("rank"),
+        }).collect();
+
+        let laps: Vec<ProfileLap> = self.client.query(
+            "SELECT map, car, score FROM lap WHERE steamid = $1 ORDER BY map, car",
+            &[&steamid],
+        ).await?.iter().map(|row| ProfileLap {
+            track: row.get("map"),
+            car: row.get("car"),
+            time: row.get("score"),
+        }).collect();
+
+        let stats: Vec<TrackStats> = self.client.query(
+            "SELECT track, total_time, avgspeed, collisions, distance FROM driver_stats WHERE steamid = $1 ORDER BY track",
+            &[&steamid],
+        ).await?.iter().map(|row| TrackStats {
+            track: row.get("track"),
+            time: row.get("total_time"),
+            avgspeed: row.get("avgspeed"),
+            collisions: row.get("collisions"),
+            distance: row.get("distance"),
+        }).collect();
+
+        let links = self.client.query(
+            "SELECT discordid, display_name, public_profile FROM steamids WHERE steamid = $1",
+            &[&steamid],
+        ).await?;
+
+        if scores.is_empty() && laps.is_empty() && stats.is_empty() && links.is_empty() {
+            return Ok(None)
+        }
+        let public = links.iter().find(|row| row.get::<&str, bool>("public_profile"));
+        Ok(Some(PlayerProfile {
+            steamid,
+            name: public.and_then(|row| row.get("display_name")),
+            discordid: public.map(|row| row.get("discordid")),
+            scores,
+            laps,
+            stats,
+        }))
+    }
+
+    // Tracks with the cars driven on them, from cutup scores and laps
+    pub async fn tracks(&self) -> Result<Vec<Track>, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["tracks"]).start_timer();
+        let rows = self.client.query(
+            "SELECT track, array_agg(DISTINCT car ORDER BY car) AS cars FROM (
+                SELECT track, car FROM cutup UNION SELECT map, car FROM lap
+            ) driven GROUP BY track ORDER BY track",
+            &[],
+        ).await?;
+        Ok(rows.iter().map(|row| Track { name: row.get("track"), cars: row.get("cars") }).collect())
+    }
+}
@@ -0,0 +1,110 @@
+use tokio_postgres::{Error, GenericClient};
+
+// A cutup score which improved the driver's personal best
+#[derive(Debug, Clone)]
+pub struct Highscore {
+    pub steamid: i64,
+    pub track: String,
+    pub car: String,
+    pub score: i64,
+    // Previous personal best with this car on this track
+    pub previous: Option<i64>,
+    // Steamid and score of the track record before this score
+    pub previous_record: Option<(i64, i64)>,
+    // Position on the track leaderboard after inserting
+    pub rank: i64,
+}
+
+impl Highscore {
+    pub fn is_server_record(&self) -> bool {
+        match self.previous_record {
+            Some((_, record)) => self.score > record,
+            None => true,
+        }
+    }
+}
+
+// The statements below take any client, the API runs them in a transaction through `Database::apply_event` and
+// `Database::ingest`. `server_id` is the registered game server which submitted the row, None for anonymous writes.
+
+// Has to run on the transaction connection of `Database`: writes run there one at a time, so the previous best, the
+// record and the rank can't change between reading and writing them.
+pub async fn insert_cutup_score<C: GenericClient + Sync>(client: &C, steamid: i64, track: &str, car: &str, score: i64, server_id: Option<i32>) -> Result<Option<Highscore>, Error> {
+    // Every accepted score is kept so season leaderboards can be built for any time range
+    client.execute(
+        "INSERT INTO cutup_history (steamid, track, car, score, server_id) VALUES ($1, $2, $3, $4, $5)",
+        &[&steamid, &track, &car, &score, &server_id],
+    ).await?;
+
+    let previous: Option<i64> = client.query(
+        "SELECT score FROM cutup WHERE steamid = $1 AND track = $2 AND car = $3",
+        &[&steamid, &track, &car],
+    ).await?.first().map(|row| row.get(0));
+
+    if previous.map_or(false, |previous| previous >= score) {
+        return Ok(None)
+    }
+
+    let previous_record: Option<(i64, i64)> = client.query(
+        "SELECT steamid, score FROM cutup WHERE track = $1 ORDER BY score DESC LIMIT 1",
+        &[&track],
+    ).await?.first().map(|row| (row.get(0), row.get(1)));
+
+    let changed = client.execute(
+        "INSERT INTO cutup (steamid, track, car, score, server_id)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (steamid, track, car)
+        DO UPDATE SET score = EXCLUDED.score, server_id = EXCLUDED.server_id
+        WHERE EXCLUDED.score > cutup.score;",
+        &[&steamid, &track, &car, &score, &server_id],
+    ).await?;
+    if changed == 0 {
+        return Ok(None)
+    }
+
+    let rank: i64 = client.query_one(
+        "SELECT COUNT(*) FROM cutup WHERE track = $1 AND score > $2",
+        &[&track, &score],
+    ).await?.get(0);
+
+    Ok(Some(Highscore {
+        steamid,
+        track: track.to_string(),
+        car: car.to_string(),
+        score,
+        previous,
+        previous_record,
+        rank: rank + 1,
+    }))
+}
+
+// Stats add up over all servers, `server_id` is the one which sent the latest session
+pub async fn add_driver_stats<C: GenericClient + Sync>(client: &C, steamid: i64, track: &str, time: i64, avgspeed: i64, collisions: i64, distance: f64, server_id: Option<i32>) -> Result<(), Error> {
+    client.execute(
+        "INSERT INTO driver_stats (steamid, track, collisions, distance, avgspeed, total_time, server_id)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (steamid, track)
+         DO UPDATE SET collisions = driver_stats.collisions + EXCLUDED.collisions,
+                       distance = driver_stats.distance + EXCLUDED.distance,
+                       avgspeed = (driver_stats.avgspeed + EXCLUDED.avgspeed) / 2,
+                       total_time = driver_stats.total_time + EXCLUDED.total_time,
+                       server_id = EXCLUDED.server_id
+         WHERE driver_stats.steamid = EXCLUDED.steamid AND
+               driver_stats.track = EXCLUDED.track",
+        &[&steamid, &track, &collisions, &distance, &avgspeed, &time, &server_id],
+    ).await?;
+    Ok(())
+}
+
+// Keeps the fastest lap per steamid, track and car, returns whether the time improved
+pub async fn insert_lap<C: GenericClient + Sync>(client: &C, steamid: i64, track: &str, car: &str, time: i64, server_id: Option<i32>) -> Result<bool, Error> {
+    let changed = client.execute(
+        "INSERT INTO lap (steamid, map, car, score, server_id)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (steamid, map, car)
+        DO UPDATE SET score = EXCLUDED.score, server_id = EXCLUDED.server_id
+        WHERE EXCLUDED.score < lap.score",
+        &[&steamid, &track, &car, &time, &server_id],
+    ).await?;
+    Ok(changed > 0)
+}
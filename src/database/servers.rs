@@ -0,0 +1,93 @@
+use std::time::SystemTime;
+use sha2::{Digest, Sha256};
+use tokio_postgres::{Error, Row};
+
+use super::Database;
+use crate::metrics::DB_QUERY_DURATION;
+
+const COLUMNS: &str = "id, name, region, allowed_tracks, enabled, created_at";
+
+// A game server of the registry, identified by the API key it sends as `x-api-key`
+#[derive(Debug, Clone)]
+pub struct GameServer {
+    pub id: i32,
+    pub name: String,
+    pub region: String,
+    // Tracks the server may submit results for, any track if empty
+    pub allowed_tracks: Vec<String>,
+    pub enabled: bool,
+    pub created_at: SystemTime,
+}
+
+impl GameServer {
+    fn from_row(row: &Row) -> GameServer {
+        GameServer {
+            id: row.get("id"),
+            name: row.get("name"),
+            region: row.get("region"),
+            allowed_tracks: row.get("allowed_tracks"),
+            enabled: row.get("enabled"),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    pub fn allows(&self, track: &str) -> bool {
+        self.allowed_tracks.is_empty() || self.allowed_tracks.iter().any(|allowed| allowed == track)
+    }
+}
+
+// Only the hash of a key is stored, the key itself is shown once when it is created
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn generate_key() -> String {
+    format!("swsrv_{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+impl Database {
+    pub async fn game_servers(&self) -> Result<Vec<GameServer>, Error> {
+        let rows = self.query(&*format!("SELECT {} FROM servers ORDER BY name", COLUMNS), &[]).await?;
+        Ok(rows.iter().map(GameServer::from_row).collect())
+    }
+
+    // Key hashes with the id of their server, for telling rate limited clients apart
+    pub async fn game_server_keys(&self) -> Result<Vec<(String, i32)>, Error> {
+        let rows = self.query("SELECT api_key_hash, id FROM servers", &[]).await?;
+        Ok(rows.iter().map(|row| (row.get("api_key_hash"), row.get("id"))).collect())
+    }
+
+    // Checked on writes without a key while `api.require_server_key` is unset
+    pub async fn has_game_servers(&self) -> Result<bool, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["has_game_servers"]).start_timer();
+        Ok(self.client.query_one("SELECT EXISTS (SELECT 1 FROM servers)", &[]).await?.get(0))
+    }
+
+    // Looked up on every write, disabling a server takes effect right away
+    pub async fn game_server_by_key(&self, key: &str) -> Result<Option<GameServer>, Error> {
+        let _timer = DB_QUERY_DURATION.with_label_values(&["game_server_by_key"]).start_timer();
+        let rows = self.client.query(&*format!("SELECT {} FROM servers WHERE api_key_hash = $1", COLUMNS), &[&hash_key(key)]).await?;
+        Ok(rows.first().map(GameServer::from_row))
+    }
+
+    pub async fn insert_game_server(&self, name: &str, region: &str, key: &str, allowed_tracks: &[String]) -> Result<GameServer, Error> {
+        let row = self.query_one(
+            &*format!("INSERT INTO servers (name, region, api_key_hash, allowed_tracks) VALUES ($1, $2, $3, $4) RETURNING {}", COLUMNS),
+            &[&name, &region, &hash_key(key), &allowed_tracks],
+        ).await?;
+        Ok(GameServer::from_row(&row))
+    }
+
+    // The update functions return whether a server with the name exists
+    pub async fn set_game_server_enabled(&self, name: &str, enabled: bool) -> Result<bool, Error> {
+        Ok(!self.query("UPDATE servers SET enabled = $2 WHERE name = $1 RETURNING id", &[&name, &enabled]).await?.is_empty())
+    }
+
+    pub async fn set_game_server_tracks(&self, name: &str, allowed_tracks: &[String]) -> Result<bool, Error> {
+        Ok(!self.query("UPDATE servers SET allowed_tracks = $2 WHERE name = $1 RETURNING id", &[&name, &allowed_tracks]).await?.is_empty())
+    }
+
+    pub async fn set_game_server_key(&self, name: &str, key: &str) -> Result<bool, Error> {
+        Ok(!self.query("UPDATE servers SET api_key_hash = $2 WHERE name = $1 RETURNING id", &[&name, &hash_key(key)]).await?.is_empty())
+    }
+}
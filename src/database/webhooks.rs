@@ -0,0 +1,86 @@
+use std::time::SystemTime;
+use serde_json::Value;
+use tokio_postgres::types::Json;
+use tokio_postgres::{Error, Row};
+
+use super::Database;
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    pub id: i32,
+    pub url: String,
+    // Event names the webhook is sent, see `webhooks::event_names`
+    pub events: Vec<String>,
+    // Key of the HMAC signature, only shown once when the webhook is added
+    pub secret: String,
+    pub created_by: i64,
+}
+
+impl Webhook {
+    fn from_row(row: &Row) -> Webhook {
+        Webhook {
+            id: row.get("id"),
+            url: row.get("url"),
+            events: row.get("events"),
+            secret: row.get("secret"),
+            created_by: row.get("created_by"),
+        }
+    }
+}
+
+// A delivery which failed every attempt
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event: String,
+    pub attempts: i32,
+    pub error: String,
+    pub failed_at: SystemTime,
+}
+
+impl Database {
+    pub async fn webhooks(&self) -> Result<Vec<Webhook>, Error> {
+        let rows = self.query("SELECT id, url, events, secret, created_by FROM webhooks ORDER BY id", &[]).await?;
+        Ok(rows.iter().map(Webhook::from_row).collect())
+    }
+
+    pub async fn insert_webhook(&self, url: &str, events: &[String], secret: &str, created_by: i64) -> Result<Webhook, Error> {
+        let row = self.query_one(
+            "INSERT INTO webhooks (url, events, secret, created_by) VALUES ($1, $2, $3, $4)
+            RETURNING id, url, events, secret, created_by",
+            &[&url, &events, &secret, &created_by],
+        ).await?;
+        Ok(Webhook::from_row(&row))
+    }
+
+    // Returns whether a webhook was removed, its dead letters go with it
+    pub async fn delete_webhook(&self, id: i32) -> Result<bool, Error> {
+        Ok(!self.query("DELETE FROM webhooks WHERE id = $1 RETURNING id", &[&id]).await?.is_empty())
+    }
+
+    pub async fn insert_dead_letter(&self, webhook_id: i32, event: &str, payload: &Value, attempts: i32, error: &str) -> Result<(), Error> {
+        self.execute(
+            "INSERT INTO webhook_dead_letters (webhook_id, event, payload, attempts, error) VALUES ($1, $2, $3, $4, $5)",
+            &[&webhook_id, &event, &Json(payload), &attempts, &error],
+        ).await
+    }
+
+    // Newest first, of one webhook or of all
+    pub async fn dead_letters(&self, webhook_id: Option<i32>, limit: i64) -> Result<Vec<DeadLetter>, Error> {
+        let rows = self.query(
+            "SELECT id, webhook_id, event, attempts, error, failed_at FROM webhook_dead_letters
+            WHERE $1::INTEGER IS NULL OR webhook_id = $1
+            ORDER BY failed_at DESC LIMIT $2",
+            &[&webhook_id, &limit],
+        ).await?;
+        Ok(rows.iter().map(|row| DeadLetter {
+            id: row.get("id"),
+            webhook_id: row.get("webhook_id"),
+            event: row.get("event"),
+            attempts: row.get("attempts"),
+            error: row.get("error"),
+            failed_at: row.get("failed_at"),
+        }).collect())
+    }
+}
@@ -0,0 +1,36 @@
+use poise::serenity_prelude as serenity;
+use crate::{Context, Error};
+use super::commands::reply;
+
+// Owners from the config (read live, so reloads apply) and the application owners known to poise
+pub async fn owner_check(ctx: Context<'_>) -> Result<bool, Error> {
+    let author = ctx.author().id;
+    let config = ctx.data().config.get();
+    Ok(config.discord.owners.iter().any(|owner| serenity::UserId::new(*owner) == author)
+        || ctx.framework().options().owners.contains(&author))
+}
+
+/// Bot administration
+#[poise::command(slash_command, prefix_command, hide_in_help, check = "owner_check", subcommands("reload"))]
+pub async fn admin(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Reloads the config file
+#[poise::command(slash_command, prefix_command, check = "owner_check")]
+pub async fn reload(ctx: Context<'_>) -> Result<(), Error> {
+    log::info!("{} requested a config reload", ctx.author().name);
+    match ctx.data().config.reload() {
+        Ok(restart_required) if restart_required.is_empty() => {
+            reply(&ctx, "Config reloaded.".to_string()).await?;
+        },
+        Ok(restart_required) => {
+            reply(&ctx, format!("Config reloaded. These changes need a restart: {}", restart_required.join(", "))).await?;
+        },
+        Err(e) => {
+            log::error!("Failed to reload config: {}", e);
+            reply(&ctx, format!("Failed to reload config: {}", e)).await?;
+        }
+    }
+    Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude as ser;
+use crate::metrics;
+use crate::database::Database;
+use crate::database::scores::Highscore;
+use super::config::AnnouncementConfig;
+
+#[derive(Debug)]
+pub struct Announcer {
+    // When each steamid was last announced, used to throttle spam
+    announced: Mutex<HashMap<i64, Instant>>,
+}
+
+impl Announcer {
+    pub fn new() -> Announcer {
+        Announcer {
+            announced: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Checks whether the highscore is worth an announcement and records it if so
+    fn should_announce(&self, config: &AnnouncementConfig, highscore: &Highscore) -> bool {
+        let server_record = highscore.is_server_record();
+        if !server_record && !config.personal_bests && highscore.rank > config.top_n {
+            return false
+        }
+
+        let mut announced = self.announced.lock().unwrap();
+        let throttle = Duration::from_secs(config.throttle);
+        announced.retain(|_, at| at.elapsed() < throttle);
+        if !server_record && announced.contains_key(&highscore.steamid) {
+            log::debug!("Not announcing highscore of {}, throttled", highscore.steamid);
+            return false
+        }
+        announced.insert(highscore.steamid, Instant::now());
+        true
+    }
+
+    pub async fn announce(&self, http: &ser::Http, database: &Database, config: &AnnouncementConfig, highscore: &Highscore) {
+        let channel = match config.channel_for(&highscore.track) {
+            Some(channel) => ser::ChannelId::new(channel),
+            None => return,
+        };
+        if !self.should_announce(config, highscore) {
+            return
+        }
+
+        let driver = mention(database, highscore.steamid).await;
+        let title = if highscore.is_server_record() {
+            format!("New server record on {}!", highscore.track)
+        } else if highscore.rank <= config.top_n {
+            format!("New #{} score on {}!", highscore.rank, highscore.track)
+        } else {
+            "New personal best!".to_string()
+        };
+
+        let mut embed = ser::CreateEmbed::default()
+            .title(title)
+            .description(format!("{} scored **{}** on {} with {}", driver, highscore.score, highscore.track, highscore.car))
+            .color(ser::Colour::from_rgb(255, 255, 255));
+
+        match highscore.previous {
+            Some(previous) => {
+                embed = embed
+                    .field("Previous best", previous.to_string(), true)
+                    .field("Margin", format!("+{}", highscore.score - previous), true);
+            },
+            None => {
+                embed = embed.field("Previous best", "None", true);
+            }
+        }
+        if highscore.is_server_record() {
+            if let Some((holder, record)) = highscore.previous_record {
+                let holder = mention(database, holder).await;
+                embed = embed.field("Previous record", format!("{} by {} (+{})", record, holder, highscore.score - record), false);
+            }
+        }
+
+        let msg = ser::CreateMessage::default()
+            .embeds(vec![embed]);
+        if let Err(e) = channel.send_message(http, msg).await {
+            log::error!("Failed to announce highscore in channel {}: {}", channel, e);
+            metrics::discord_error("announcements");
+        }
+    }
+}
+
+// Mentions the discord member linked to a steamid, falls back to the steamid
+pub async fn mention(database: &Database, steamid: i64) -> String {
+    match database.query_one("SELECT discordid FROM steamids WHERE steamid = $1", &[&steamid]).await {
+        Ok(row) => format!("<@{}>", row.get::<usize, i64>(0)),
+        Err(_) => format!("steamid {}", steamid),
+    }
+}
@@ -1,10 +1,11 @@
 use poise::serenity_prelude as serenity;
-use crate::{Context, Error};
+use crate::{Context, Data, Error};
+use crate::bus::BotEvent;
 use poise::command;
 
 use poise::reply;
 
-async fn reply(ctx: &Context<'_>, message: String) -> Result<(), Error> {
+pub(crate) async fn reply(ctx: &Context<'_>, message: String) -> Result<(), Error> {
     Ok(_ = ctx.send(reply::CreateReply::default()
             .embed(poise::serenity_prelude::CreateEmbed::default()
                 .title("swim> bot")
@@ -14,6 +15,27 @@ async fn reply(ctx: &Context<'_>, message: String) -> Result<(), Error> {
         ).await?)
 }
 
+// Every command the bot registers
+pub fn all() -> Vec<poise::Command<Data, Error>> {
+    vec![
+        register(),
+        help(),
+        ping(),
+        link(),
+        steamid(),
+        profile(),
+        score(),
+        super::moderation::ban(),
+        super::moderation::unban(),
+        super::moderation::bans(),
+        super::status::servers(),
+        super::notifications::notifications(),
+        super::admin::admin(),
+        super::webhooks::webhook(),
+        super::gameservers::gameserver(),
+    ]
+}
+
 /// Responds with pong
 #[command(slash_command, prefix_command)]
 pub async fn ping(ctx: Context<'_>) -> Result<(), Error> {
@@ -63,6 +85,7 @@ pub async fn link(ctx: Context<'_>, steamid: i64) -> Result<(), Error> {
         ",
         &[&steamid, &discordid],
     ).await?;
+    ctx.data().bus.publish(BotEvent::Link { discordid, steamid });
     if existing_discordid == 0 {
         reply(&ctx, format!("Linked steamid: {}", steamid)).await?;
     } else {
@@ -100,6 +123,45 @@ pub async fn steamid(ctx: Context<'_>, user: Option<serenity::model::user::User>
     Ok(())
 }
 
+/// Choose whether the website shows your discord name next to your scores
+#[poise::command(slash_command, subcommands("profile_public", "profile_private"))]
+pub async fn profile(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Show your discord name on the website
+#[poise::command(slash_command, rename = "public")]
+pub async fn profile_public(ctx: Context<'_>) -> Result<(), Error> {
+    set_public_profile(ctx, true).await
+}
+
+/// Hide your discord name on the website, your scores stay listed under your steamid
+#[poise::command(slash_command, rename = "private")]
+pub async fn profile_private(ctx: Context<'_>) -> Result<(), Error> {
+    set_public_profile(ctx, false).await
+}
+
+// The display name is stored with the choice, so the website never has to ask discord for it
+async fn set_public_profile(ctx: Context<'_>, public: bool) -> Result<(), Error> {
+    let display_name = match ctx.author_member().await {
+        Some(member) if public => Some(member.display_name().to_string()),
+        _ if public => Some(ctx.author().name.clone()),
+        _ => None,
+    };
+    let updated = ctx.data().database.query(
+        "UPDATE steamids SET public_profile = $2, display_name = $3 WHERE discordid = $1 RETURNING steamid",
+        &[&i64::from(ctx.author().id), &public, &display_name],
+    ).await?;
+    if updated.is_empty() {
+        reply(&ctx, "Link your steamid with /link first.".to_string()).await?;
+    } else if public {
+        reply(&ctx, "Your discord name is now shown on the website.".to_string()).await?;
+    } else {
+        reply(&ctx, "Your discord name is no longer shown on the website.".to_string()).await?;
+    }
+    Ok(())
+}
+
 /// Fetch highscore with optional parameters
 #[poise::command(slash_command, prefix_command)]
 pub async fn score(ctx: Context<'_>, user: Option<serenity::model::user::User>) -> Result<(), Error> {
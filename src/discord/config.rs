@@ -1,5 +1,8 @@
+use std::collections::HashMap;
 use serde::Deserialize;
 
+use crate::config::Issues;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct DiscordConfig {
     pub token: String,
@@ -7,4 +10,90 @@ pub struct DiscordConfig {
     pub owners: Vec<u64>,
     pub guild: u64,
     pub edit_track_timespan: u64,
+    // Seconds between leaderboard updates
+    #[serde(default = "default_leaderboard_interval")]
+    pub leaderboard_interval: u64,
+    // Role given to linked members while they have an active ban
+    #[serde(default)]
+    pub banned_role: Option<u64>,
+    // Channel in which the bot keeps a live status panel of the game servers
+    #[serde(default)]
+    pub status_channel: Option<u64>,
+    #[serde(default)]
+    pub announcements: AnnouncementConfig,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AnnouncementConfig {
+    // Channel for new highscore announcements, disabled if not set
+    pub channel: Option<u64>,
+    // Tracks which announce in their own channel
+    #[serde(default)]
+    pub track_channels: HashMap<String, u64>,
+    // Scores entering the top N of a track are announced
+    #[serde(default = "default_top_n")]
+    pub top_n: i64,
+    // Announce every personal best, not only top N scores
+    #[serde(default)]
+    pub personal_bests: bool,
+    // Minimum seconds between two announcements of the same driver, server records are always announced
+    #[serde(default = "default_throttle")]
+    pub throttle: u64,
+}
+
+fn default_leaderboard_interval() -> u64 {
+    60
+}
+
+fn default_top_n() -> i64 {
+    10
+}
+
+fn default_throttle() -> u64 {
+    300
+}
+
+impl Default for AnnouncementConfig {
+    fn default() -> Self {
+        AnnouncementConfig {
+            channel: None,
+            track_channels: HashMap::new(),
+            top_n: default_top_n(),
+            personal_bests: false,
+            throttle: default_throttle(),
+        }
+    }
+}
+
+impl DiscordConfig {
+    pub fn validate(&self, issues: &mut Issues) {
+        issues.check(!self.token.trim().is_empty(), "discord.token", "must not be empty");
+        issues.check(!self.prefix.is_empty(), "discord.prefix", "must not be empty");
+        issues.check_id(self.guild, "discord.guild");
+        issues.check(self.leaderboard_interval > 0, "discord.leaderboard_interval", "must be at least 1 second");
+        for (i, owner) in self.owners.iter().enumerate() {
+            issues.check_id(*owner, format!("discord.owners[{}]", i));
+        }
+        if let Some(role) = self.banned_role {
+            issues.check_id(role, "discord.banned_role");
+        }
+        if let Some(channel) = self.status_channel {
+            issues.check_id(channel, "discord.status_channel");
+        }
+
+        let announcements = &self.announcements;
+        if let Some(channel) = announcements.channel {
+            issues.check_id(channel, "discord.announcements.channel");
+        }
+        for (track, channel) in announcements.track_channels.iter() {
+            issues.check_id(*channel, format!("discord.announcements.track_channels.{}", track));
+        }
+        issues.check(announcements.top_n > 0, "discord.announcements.top_n", "must be at least 1");
+    }
+}
+
+impl AnnouncementConfig {
+    pub fn channel_for(&self, track: &str) -> Option<u64> {
+        self.track_channels.get(track).copied().or(self.channel)
+    }
 }
\ No newline at end of file
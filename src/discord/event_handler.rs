@@ -1,7 +1,9 @@
 use tokio::time::{sleep, Duration};
+use std::collections::HashMap;
 use std::ops::Deref;
 
-use crate::{Data, Error};
+use crate::{metrics, Data, Error};
+use crate::bus::{BotEvent, LeaderboardEntry};
 use poise::serenity_prelude as ser;
 
 pub async fn event_handler<'a>(ctx: poise::FrameworkContext<'_, Data, Error>, event: &'a ser::FullEvent) -> Result<(), Error> {
@@ -11,8 +13,9 @@ pub async fn event_handler<'a>(ctx: poise::FrameworkContext<'_, Data, Error>, ev
         ser::FullEvent::CacheReady { guilds } => {
             log::debug!("Cache ready event received");
 
-            if !guilds.iter().any(|guild| guild == &ser::GuildId::new(data.config.discord.guild)) {
-                log::warn!("Guild {} not in CacheReady object.", data.config.discord.guild);
+            let guild = data.config.get().discord.guild;
+            if !guilds.iter().any(|g| g == &ser::GuildId::new(guild)) {
+                log::warn!("Guild {} not in CacheReady object.", guild);
             }
 
             let _data = data.clone();
@@ -21,23 +24,50 @@ pub async fn event_handler<'a>(ctx: poise::FrameworkContext<'_, Data, Error>, ev
             tokio::spawn(async move {
                 let data_clone = _data.clone();
                 let ctx = _ctx.clone();
+                let mut shutdown = data_clone.shutdown.clone();
+                let mut published = HashMap::new();
 
                 loop {
                     log::debug!("Updating leaderboards!");
-                    // Your function to run every minute
-                    update_leaderboards(&ctx, &data_clone).await;
+                    let timer = metrics::LEADERBOARD_UPDATE_DURATION.start_timer();
+                    update_leaderboards(&ctx, &data_clone, &mut published).await;
+                    timer.observe_duration();
+                    data_clone.health.leaderboards_updated();
+                    super::moderation::sync_banned_roles(&ctx, &data_clone).await;
+
+                    // The interval is read every time so config reloads apply
+                    tokio::select! {
+                        _ = sleep(Duration::from_secs(data_clone.config.get().discord.leaderboard_interval)) => {},
+                        _ = shutdown.wait() => break,
+                    }
+                }
+                log::debug!("Stopped updating leaderboards");
+            });
+
+            // The status panel and presence check the config themselves on every update
+            let _data = data.clone();
+            let _ctx = ctx.clone();
 
-                    // Sleep for 1 minute
-                    sleep(Duration::from_secs(60)).await;
+            tokio::spawn(async move {
+                let mut shutdown = _data.shutdown.clone();
+                loop {
+                    super::status::update_status_panel(&_ctx, &_data).await;
+                    tokio::select! {
+                        _ = sleep(Duration::from_secs(30)) => {},
+                        _ = shutdown.wait() => break,
+                    }
                 }
             });
+
+            tokio::spawn(super::presence::presence_loop(ctx.clone(), Data::clone(&data)));
         },
         _ => {}
     }
     Ok(())
 }
 
-async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data) {
+// `published` holds the entries last sent to the event bus per leaderboard channel, only changes are published
+async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data, published: &mut HashMap<i64, Vec<LeaderboardEntry>>) {
     let http = &ctx.http;
     let cache = &ctx.cache;
     // Fetch leaderboards from database
@@ -57,31 +87,40 @@ async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data
         }
     };
 
-    ctx.shard.chunk_guild(ser::GuildId::new(*&data.config.discord.guild), None, false, ser::ChunkGuildFilter::None, None);
-    let guild = cache.guild(*&data.config.discord.guild.into()).expect("Couldnt find guild.").deref().to_owned();
+    let config = data.config.get();
+    ctx.shard.chunk_guild(ser::GuildId::new(config.discord.guild), None, false, ser::ChunkGuildFilter::None, None);
+    let guild = cache.guild(config.discord.guild.into()).expect("Couldnt find guild.").deref().to_owned();
 
     for (title, channel_id, db_query) in leaderboards {
+        // Stop between leaderboards, never halfway through editing one
+        if data.shutdown.is_shutting_down() {
+            return;
+        }
         let channel = match ser::ChannelId::from(channel_id as u64).to_channel(http).await {
             Ok(channel) => channel.guild().unwrap(),
             Err(e) => {
                 log::error!("Failed to fetch channel {}: {}", channel_id, e);
+                metrics::discord_error("leaderboards");
                 continue;
             }
         };
 
-        let role: (poise::serenity_prelude::Role, poise::serenity_prelude::RoleId) = match data.database.query_one("SELECT discord_role FROM leaderboards WHERE channel = $1", &[&channel_id]).await {
-            Ok(row) => {
-                let role_id = match row.try_get::<usize, i64>(0) {
-                    Ok(value) => poise::serenity_prelude::RoleId::from(value as u64),
-                    Err(e) => {
-                        log::error!("Failed to fetch role id from database: {}", e);
-                        continue;
-                    },
-                };
-                let role = cache.role(guild.id, role_id).as_deref().unwrap().to_owned();
-                (role, role_id)
+        // Boards without a reward role are still shown and their drivers still notified
+        let role: Option<(poise::serenity_prelude::Role, poise::serenity_prelude::RoleId)> = match data.database.query_one("SELECT discord_role FROM leaderboards WHERE channel = $1", &[&channel_id]).await {
+            Ok(row) => match row.try_get::<usize, Option<i64>>(0) {
+                Ok(Some(value)) => {
+                    let role_id = poise::serenity_prelude::RoleId::from(value as u64);
+                    let role = cache.role(guild.id, role_id).as_deref().unwrap().to_owned();
+                    Some((role, role_id))
+                },
+                Ok(None) => None,
+                Err(e) => {
+                    log::error!("Failed to fetch role id from database: {}", e);
+                    continue;
+                },
             },
-            Err(_e) => {
+            Err(e) => {
+                log::error!("Failed to fetch the role of leaderboard {}: {}", title, e);
                 continue;
             }
         };
@@ -101,6 +140,17 @@ async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data
             }
         };
 
+        let entries: Vec<LeaderboardEntry> = scores.iter().map(|(discordid, score)| LeaderboardEntry { discordid: *discordid, score: *score }).collect();
+        if published.get(&channel_id) != Some(&entries) {
+            // Nothing was published yet after a restart, so nobody is told they dropped off because of it
+            if let Some(previous) = published.get(&channel_id) {
+                let role_name = role.as_ref().map(|(role, _)| role.name.as_str());
+                super::notifications::notify_dropped(http, &data.database, &title, previous, &entries, role_name).await;
+            }
+            data.bus.publish(BotEvent::Leaderboard { title: title.clone(), entries: entries.clone() });
+            published.insert(channel_id, entries);
+        }
+
         let mut top_users = String::new();
         let mut count = 1;
 
@@ -108,20 +158,24 @@ async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data
             match guild.member(http, ser::UserId::from(*user as u64)).await {
                 Ok(member) => {
                     top_users.push_str(&format!("{}. {}: {}\n", count, member.user.name, score));
-                    if !member.roles.contains(&role.1) {
-                        match member.add_role(http, role.1).await {
-                            Ok(_) => {
-                                log::info!("Added role {} to user {}", role.0.name, member.user.name);
-                            }
-                            Err(e) => {
-                                log::error!("Failed to add role {} to user {}: {}", role.0.name, member.user.name, e);
-                                continue;
+                    if let Some((role, role_id)) = &role {
+                        if !member.roles.contains(role_id) {
+                            match member.add_role(http, *role_id).await {
+                                Ok(_) => {
+                                    log::info!("Added role {} to user {}", role.name, member.user.name);
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to add role {} to user {}: {}", role.name, member.user.name, e);
+                                    metrics::discord_error("leaderboards");
+                                    continue;
+                                }
                             }
                         }
                     }
                 },
                 Err(e) => {
                     log::error!("Failed to fetch member {}: {}", user, e);
+                    metrics::discord_error("leaderboards");
                     top_users.push_str(&format!("{}. Unknown: {}\n", count, score));
                     continue;
                 }
@@ -133,13 +187,14 @@ async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data
             Ok(messages) => messages,
             Err(e) => {
                 log::error!("Failed to fetch messages from channel {}: {}", channel_id, e);
+                metrics::discord_error("leaderboards");
                 continue;
             }
         };
 
         if messages.len() < 1 {
             let embed = ser::CreateEmbed::default()
-                .title(title)
+                .title(title.clone())
                 .description(top_users)
                 .to_owned();
 
@@ -149,7 +204,7 @@ async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data
             let _ = channel.send_message(http, msg).await;
         } else {
             let embed = ser::CreateEmbed::default()
-                .title(title)
+                .title(title.clone())
                 .description(top_users)
                 .to_owned();
 
@@ -159,17 +214,22 @@ async fn update_leaderboards(ctx: &poise::serenity_prelude::Context, data: &Data
             let _ = messages.first_mut().unwrap().edit(http, msg).await;
         }
 
+        let (role, role_id) = match &role {
+            Some(role) => role,
+            None => continue,
+        };
         for (_, member) in guild.members.iter() {
-            if !&member.roles.contains(&role.1) {
+            if !&member.roles.contains(role_id) {
                 continue;
             }
             if !scores.iter().any(|(discord_id, _)| *discord_id == i64::from(member.user.id)) {
-                match member.remove_role(http, role.1).await {
+                match member.remove_role(http, *role_id).await {
                     Ok(_) => {
-                        log::info!("Removed role {} from user {}", role.0.name, member.user.name);
+                        log::info!("Removed role {} from user {}", role.name, member.user.name);
                     }
                     Err(e) => {
-                        log::error!("Failed to remove role {} from user {}: {}", role.0.name, member.user.name, e);
+                        log::error!("Failed to remove role {} from user {}: {}", role.name, member.user.name, e);
+                        metrics::discord_error("leaderboards");
                         return;
                     }
                 }
@@ -0,0 +1,120 @@
+use crate::{Context, Error};
+use crate::database::servers::{self, GameServer};
+use super::admin::owner_check;
+use super::commands::reply;
+use super::moderation::timestamp;
+
+// "a, b" -> ["a", "b"], nothing or only commas mean every track
+fn parse_tracks(tracks: Option<String>) -> Vec<String> {
+    tracks.unwrap_or_default().split(',').map(|track| track.trim().to_string()).filter(|track| !track.is_empty()).collect()
+}
+
+fn describe_tracks(server: &GameServer) -> String {
+    if server.allowed_tracks.is_empty() {
+        "all tracks".to_string()
+    } else {
+        server.allowed_tracks.join(", ")
+    }
+}
+
+/// Registry of the game servers allowed to write to the API
+#[poise::command(slash_command, hide_in_help, check = "owner_check",
+    subcommands("gameserver_add", "gameserver_list", "gameserver_tracks", "gameserver_enable", "gameserver_disable", "gameserver_rotate_key"))]
+pub async fn gameserver(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Registers a game server, its API key is only shown once
+#[poise::command(slash_command, rename = "add", check = "owner_check")]
+pub async fn gameserver_add(
+    ctx: Context<'_>,
+    #[description = "Unique name of the server"] name: String,
+    #[description = "Region the server runs in, e.g. eu"] region: String,
+    #[description = "Comma separated tracks the server may submit for, every track if empty"] tracks: Option<String>,
+) -> Result<(), Error> {
+    let data = ctx.data();
+    if data.database.game_servers().await?.iter().any(|server| server.name == name) {
+        reply(&ctx, format!("There already is a game server named {}.", name)).await?;
+        return Ok(())
+    }
+    let key = servers::generate_key();
+    let server = data.database.insert_game_server(&name, &region, &key, &parse_tracks(tracks)).await?;
+    log::info!("{} registered game server {} ({})", ctx.author().name, server.name, server.id);
+    reply(&ctx, format!(
+        "Registered game server #{} {} in {} for {}.\nAPI key, it won't be shown again: `{}`",
+        server.id, server.name, server.region, describe_tracks(&server), key,
+    )).await?;
+    Ok(())
+}
+
+/// Lists the registered game servers
+#[poise::command(slash_command, rename = "list", check = "owner_check")]
+pub async fn gameserver_list(ctx: Context<'_>) -> Result<(), Error> {
+    let servers = ctx.data().database.game_servers().await?;
+    if servers.is_empty() {
+        reply(&ctx, "No game servers are registered.".to_string()).await?;
+        return Ok(())
+    }
+    let lines: Vec<String> = servers.iter()
+        .map(|server| format!(
+            "#{} {} ({}){}: {}, added {}",
+            server.id, server.name, server.region, if server.enabled { "" } else { " disabled" },
+            describe_tracks(server), timestamp(server.created_at),
+        ))
+        .collect();
+    reply(&ctx, lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Sets the tracks a game server may submit for
+#[poise::command(slash_command, rename = "tracks", check = "owner_check")]
+pub async fn gameserver_tracks(
+    ctx: Context<'_>,
+    #[description = "Name of the server"] name: String,
+    #[description = "Comma separated tracks, every track if empty"] tracks: Option<String>,
+) -> Result<(), Error> {
+    let tracks = parse_tracks(tracks);
+    if ctx.data().database.set_game_server_tracks(&name, &tracks).await? {
+        log::info!("{} set the tracks of game server {} to {:?}", ctx.author().name, name, tracks);
+        let tracks = if tracks.is_empty() { "all tracks".to_string() } else { tracks.join(", ") };
+        reply(&ctx, format!("{} may now submit for {}.", name, tracks)).await?;
+    } else {
+        reply(&ctx, format!("There's no game server named {}.", name)).await?;
+    }
+    Ok(())
+}
+
+/// Accepts writes of a game server again
+#[poise::command(slash_command, rename = "enable", check = "owner_check")]
+pub async fn gameserver_enable(ctx: Context<'_>, #[description = "Name of the server"] name: String) -> Result<(), Error> {
+    set_enabled(ctx, name, true).await
+}
+
+/// Refuses all writes of a game server, its past results are kept
+#[poise::command(slash_command, rename = "disable", check = "owner_check")]
+pub async fn gameserver_disable(ctx: Context<'_>, #[description = "Name of the server"] name: String) -> Result<(), Error> {
+    set_enabled(ctx, name, false).await
+}
+
+async fn set_enabled(ctx: Context<'_>, name: String, enabled: bool) -> Result<(), Error> {
+    if ctx.data().database.set_game_server_enabled(&name, enabled).await? {
+        log::info!("{} {} game server {}", ctx.author().name, if enabled { "enabled" } else { "disabled" }, name);
+        reply(&ctx, format!("{} is {}.", name, if enabled { "enabled" } else { "disabled" })).await?;
+    } else {
+        reply(&ctx, format!("There's no game server named {}.", name)).await?;
+    }
+    Ok(())
+}
+
+/// Replaces the API key of a game server, the old key stops working right away
+#[poise::command(slash_command, rename = "rotate-key", check = "owner_check")]
+pub async fn gameserver_rotate_key(ctx: Context<'_>, #[description = "Name of the server"] name: String) -> Result<(), Error> {
+    let key = servers::generate_key();
+    if ctx.data().database.set_game_server_key(&name, &key).await? {
+        log::info!("{} rotated the API key of game server {}", ctx.author().name, name);
+        reply(&ctx, format!("New API key of {}, it won't be shown again: `{}`", name, key)).await?;
+    } else {
+        reply(&ctx, format!("There's no game server named {}.", name)).await?;
+    }
+    Ok(())
+}
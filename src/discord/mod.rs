@@ -1,3 +1,11 @@
 pub mod config;
 pub mod commands;
-pub mod event_handler;
\ No newline at end of file
+pub mod event_handler;
+pub mod announcements;
+pub mod admin;
+pub mod moderation;
+pub mod notifications;
+pub mod presence;
+pub mod status;
+pub mod webhooks;
+pub mod gameservers;
\ No newline at end of file
@@ -0,0 +1,182 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use poise::serenity_prelude as serenity;
+use crate::metrics;
+use crate::{Context, Data, Error};
+use crate::database::bans::{Ban, SCOPES};
+use crate::bus::BotEvent;
+use super::commands::reply;
+
+// Formats a time as a relative discord timestamp
+pub(crate) fn timestamp(time: SystemTime) -> String {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => format!("<t:{}:R>", duration.as_secs()),
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+fn describe_ban(ban: &Ban) -> String {
+    let expires = match ban.expires_at {
+        Some(expires_at) => format!("expires {}", timestamp(expires_at)),
+        None => "permanent".to_string(),
+    };
+    format!("#{} steamid {} ({}, {}) by <@{}> {}: {}", ban.id, ban.steamid, ban.scope, expires, ban.issuer, timestamp(ban.created_at), ban.reason)
+}
+
+async fn resolve_steamid(ctx: &Context<'_>, user: Option<&serenity::User>, steamid: Option<i64>) -> Option<i64> {
+    if let Some(steamid) = steamid {
+        return Some(steamid)
+    }
+    let discordid = i64::from(user?.id);
+    match ctx.data().database.query_one("SELECT steamid FROM steamids WHERE discordid = $1", &[&discordid]).await {
+        Ok(row) => Some(row.get(0)),
+        Err(e) => {
+            log::error!("Failed to fetch steamid: {}", e);
+            None
+        }
+    }
+}
+
+// Gives or removes the configured banned role from the discord account linked to the steamid
+pub async fn set_banned_role(http: &serenity::Http, data: &Data, steamid: i64, banned: bool) {
+    let config = data.config.get();
+    let role = match config.discord.banned_role {
+        Some(role) => serenity::RoleId::new(role),
+        None => return,
+    };
+    let discordid: i64 = match data.database.query_one("SELECT discordid FROM steamids WHERE steamid = $1", &[&steamid]).await {
+        Ok(row) => row.get(0),
+        Err(_) => return,
+    };
+    let guild = serenity::GuildId::new(config.discord.guild);
+    let user = serenity::UserId::new(discordid as u64);
+    let result = if banned {
+        http.add_member_role(guild, user, role, Some("swim> ban")).await
+    } else {
+        http.remove_member_role(guild, user, role, Some("swim> unban")).await
+    };
+    if let Err(e) = result {
+        log::error!("Failed to update banned role of user {}: {}", discordid, e);
+        metrics::discord_error("moderation");
+    }
+}
+
+// Removes the banned role from members whose bans ran out or were revoked elsewhere
+pub async fn sync_banned_roles(ctx: &serenity::Context, data: &Data) {
+    let config = data.config.get();
+    let role = match config.discord.banned_role {
+        Some(role) => serenity::RoleId::new(role),
+        None => return,
+    };
+    let members: Vec<serenity::UserId> = match ctx.cache.guild(serenity::GuildId::new(config.discord.guild)) {
+        Some(guild) => guild.members
+            .iter()
+            .filter(|(_, member)| member.roles.contains(&role))
+            .map(|(_, member)| member.user.id)
+            .collect(),
+        None => return,
+    };
+
+    for user in members {
+        let discordid = i64::from(user);
+        let steamid: i64 = match data.database.query_one("SELECT steamid FROM steamids WHERE discordid = $1", &[&discordid]).await {
+            Ok(row) => row.get(0),
+            Err(_) => continue,
+        };
+        match data.database.active_ban(steamid, "all").await {
+            Ok(Some(_)) => {},
+            Ok(None) => {
+                log::info!("Ban of steamid {} is over, removing banned role", steamid);
+                set_banned_role(&ctx.http, data, steamid, false).await;
+            },
+            Err(e) => log::error!("Failed to fetch bans for steamid {}: {}", steamid, e),
+        }
+    }
+}
+
+/// Bans a driver from the swim servers
+#[poise::command(slash_command, required_permissions = "BAN_MEMBERS", default_member_permissions = "BAN_MEMBERS")]
+pub async fn ban(
+    ctx: Context<'_>,
+    #[description = "Reason for the ban"] reason: String,
+    #[description = "Discord user to ban"] user: Option<serenity::User>,
+    #[description = "Steamid to ban"] steamid: Option<i64>,
+    #[description = "Ban duration, e.g. 12h or 7d (permanent if empty)"] duration: Option<String>,
+    #[description = "What the ban applies to: all, servers or scores"] scope: Option<String>,
+) -> Result<(), Error> {
+    let steamid = match resolve_steamid(&ctx, user.as_ref(), steamid).await {
+        Some(steamid) => steamid,
+        None => {
+            reply(&ctx, "Give either a steamid or a discord user with a linked steamid.".to_string()).await?;
+            return Ok(())
+        }
+    };
+    let scope = scope.unwrap_or_else(|| "all".to_string()).to_lowercase();
+    if !SCOPES.contains(&scope.as_str()) {
+        reply(&ctx, format!("Unknown scope {}, use one of: {}", scope, SCOPES.join(", "))).await?;
+        return Ok(())
+    }
+    let expires_at = match duration {
+        Some(duration) => match humantime::parse_duration(&duration) {
+            Ok(duration) => Some(SystemTime::now() + duration),
+            Err(e) => {
+                reply(&ctx, format!("Invalid duration {}: {}", duration, e)).await?;
+                return Ok(())
+            }
+        },
+        None => None,
+    };
+
+    let ban = ctx.data().database.insert_ban(steamid, &reason, i64::from(ctx.author().id), &scope, expires_at).await?;
+    log::info!("{} banned steamid {}: {:?}", ctx.author().name, steamid, ban);
+    ctx.data().bus.publish(BotEvent::from(&ban));
+    if ban.scope == "all" {
+        set_banned_role(ctx.http(), ctx.data(), steamid, true).await;
+    }
+    reply(&ctx, format!("Banned {}", describe_ban(&ban))).await?;
+    Ok(())
+}
+
+/// Lifts all active bans of a driver
+#[poise::command(slash_command, required_permissions = "BAN_MEMBERS", default_member_permissions = "BAN_MEMBERS")]
+pub async fn unban(
+    ctx: Context<'_>,
+    #[description = "Discord user to unban"] user: Option<serenity::User>,
+    #[description = "Steamid to unban"] steamid: Option<i64>,
+) -> Result<(), Error> {
+    let steamid = match resolve_steamid(&ctx, user.as_ref(), steamid).await {
+        Some(steamid) => steamid,
+        None => {
+            reply(&ctx, "Give either a steamid or a discord user with a linked steamid.".to_string()).await?;
+            return Ok(())
+        }
+    };
+
+    let revoked = ctx.data().database.revoke_bans(steamid).await?;
+    if revoked == 0 {
+        reply(&ctx, format!("Steamid {} has no active bans.", steamid)).await?;
+        return Ok(())
+    }
+    log::info!("{} unbanned steamid {}", ctx.author().name, steamid);
+    ctx.data().bus.publish(BotEvent::Unban { steamid, revoked });
+    set_banned_role(ctx.http(), ctx.data(), steamid, false).await;
+    reply(&ctx, format!("Revoked {} ban(s) of steamid {}", revoked, steamid)).await?;
+    Ok(())
+}
+
+/// Lists all active bans
+#[poise::command(slash_command, required_permissions = "BAN_MEMBERS", default_member_permissions = "BAN_MEMBERS")]
+pub async fn bans(ctx: Context<'_>) -> Result<(), Error> {
+    let bans = ctx.data().database.active_bans().await?;
+    if bans.is_empty() {
+        reply(&ctx, "There are no active bans.".to_string()).await?;
+        return Ok(())
+    }
+    let lines: Vec<String> = bans.iter().take(20).map(describe_ban).collect();
+    let mut message = lines.join("\n");
+    if bans.len() > lines.len() {
+        message.push_str(&format!("\n...and {} more", bans.len() - lines.len()));
+    }
+    reply(&ctx, message).await?;
+    Ok(())
+}
@@ -0,0 +1,223 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use poise::serenity_prelude as ser;
+use crate::metrics;
+use crate::{Context, Error};
+use crate::database::Database;
+use crate::database::scores::Highscore;
+use crate::bus::LeaderboardEntry;
+use super::commands::reply;
+
+// Board name used for record notifications, next to the leaderboard titles
+pub const RECORDS: &str = "records";
+
+#[derive(Debug, Clone)]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    // Leaderboard titles to be notified about, all if None
+    pub boards: Option<Vec<String>>,
+    // UTC hours between which no DMs are sent
+    pub quiet_hours: Option<(i16, i16)>,
+}
+
+impl NotificationSettings {
+    pub async fn fetch(database: &Database, discordid: i64) -> Result<NotificationSettings, tokio_postgres::Error> {
+        let rows = database.query(
+            "SELECT enabled, boards, quiet_start, quiet_end FROM notification_settings WHERE discordid = $1",
+            &[&discordid],
+        ).await?;
+        Ok(match rows.first() {
+            Some(row) => NotificationSettings {
+                enabled: row.get(0),
+                boards: row.get(1),
+                quiet_hours: match (row.get::<usize, Option<i16>>(2), row.get::<usize, Option<i16>>(3)) {
+                    (Some(start), Some(end)) => Some((start, end)),
+                    _ => None,
+                },
+            },
+            // Notifications are opt-in
+            None => NotificationSettings { enabled: false, boards: None, quiet_hours: None },
+        })
+    }
+
+    fn wants(&self, board: &str, hour: i16) -> bool {
+        if !self.enabled {
+            return false
+        }
+        if let Some(boards) = &self.boards {
+            if !boards.iter().any(|b| b.eq_ignore_ascii_case(board)) {
+                return false
+            }
+        }
+        match self.quiet_hours {
+            Some((start, end)) if start <= end => !(start <= hour && hour < end),
+            // Quiet hours spanning midnight, e.g. 22 to 7
+            Some((start, end)) => !(hour >= start || hour < end),
+            None => true,
+        }
+    }
+}
+
+fn current_hour() -> i16 {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    ((secs / 3600) % 24) as i16
+}
+
+// Sends a DM to the member if they opted in for notifications about the board
+pub async fn notify(http: &ser::Http, database: &Database, discordid: i64, board: &str, message: String) {
+    let settings = match NotificationSettings::fetch(database, discordid).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            log::error!("Failed to fetch notification settings of {}: {}", discordid, e);
+            return;
+        }
+    };
+    if !settings.wants(board, current_hour()) {
+        return;
+    }
+
+    let embed = ser::CreateEmbed::default()
+        .title("swim> bot")
+        .description(message)
+        .color(ser::Colour::from_rgb(255, 255, 255));
+    let msg = ser::CreateMessage::default()
+        .embeds(vec![embed]);
+    match ser::UserId::new(discordid as u64).direct_message(http, msg).await {
+        Ok(_) => log::debug!("Sent {} notification to {}", board, discordid),
+        Err(e) => {
+            log::warn!("Failed to send notification to {}: {}", discordid, e);
+            metrics::discord_error("notifications");
+        },
+    }
+}
+
+// Lets the members who were on the board when it was last published, but aren't anymore, know they dropped off
+pub async fn notify_dropped(http: &ser::Http, database: &Database, board: &str, previous: &[LeaderboardEntry], entries: &[LeaderboardEntry], role: Option<&str>) {
+    let dropped = previous.iter()
+        .filter(|entry| !entries.iter().any(|current| current.discordid == entry.discordid));
+    for entry in dropped {
+        let message = match role {
+            Some(role) => format!("You dropped off the {} leaderboard and lost the {} role.", board, role),
+            None => format!("You dropped off the {} leaderboard.", board),
+        };
+        notify(http, database, entry.discordid, board, message).await;
+    }
+}
+
+// Lets the previous holder of a track record know it was beaten
+pub async fn notify_record_lost(http: &ser::Http, database: &Database, highscore: &Highscore) {
+    let (holder, record) = match highscore.previous_record {
+        Some(previous) if highscore.is_server_record() && previous.0 != highscore.steamid => previous,
+        _ => return,
+    };
+    let discordid: i64 = match database.query_one("SELECT discordid FROM steamids WHERE steamid = $1", &[&holder]).await {
+        Ok(row) => row.get(0),
+        Err(_) => return,
+    };
+    notify(http, database, discordid, RECORDS, format!(
+        "Your record of {} on {} was beaten with a score of {}.",
+        record, highscore.track, highscore.score,
+    )).await;
+}
+
+/// Manage direct message notifications about leaderboards
+#[poise::command(slash_command, subcommands("notifications_on", "notifications_off", "notifications_boards", "notifications_quiet", "notifications_show"))]
+pub async fn notifications(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Get a DM when you drop off a leaderboard or lose a record
+#[poise::command(slash_command, rename = "on")]
+pub async fn notifications_on(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.data().database.execute(
+        "INSERT INTO notification_settings (discordid, enabled) VALUES ($1, TRUE)
+        ON CONFLICT (discordid) DO UPDATE SET enabled = TRUE",
+        &[&i64::from(ctx.author().id)],
+    ).await?;
+    reply(&ctx, "Notifications enabled.".to_string()).await?;
+    Ok(())
+}
+
+/// Stop all notification DMs
+#[poise::command(slash_command, rename = "off")]
+pub async fn notifications_off(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.data().database.execute(
+        "INSERT INTO notification_settings (discordid, enabled) VALUES ($1, FALSE)
+        ON CONFLICT (discordid) DO UPDATE SET enabled = FALSE",
+        &[&i64::from(ctx.author().id)],
+    ).await?;
+    reply(&ctx, "Notifications disabled.".to_string()).await?;
+    Ok(())
+}
+
+/// Choose which leaderboards to be notified about
+#[poise::command(slash_command, rename = "boards")]
+pub async fn notifications_boards(
+    ctx: Context<'_>,
+    #[description = "Comma separated leaderboard titles and/or \"records\", empty for all"] boards: Option<String>,
+) -> Result<(), Error> {
+    // Only separators, e.g. ",", mean all boards as well instead of none
+    let boards: Option<Vec<String>> = boards
+        .map(|boards| boards
+            .split(',')
+            .map(|board| board.trim().to_string())
+            .filter(|board| !board.is_empty())
+            .collect::<Vec<_>>())
+        .filter(|boards| !boards.is_empty());
+    ctx.data().database.execute(
+        "INSERT INTO notification_settings (discordid, boards) VALUES ($1, $2)
+        ON CONFLICT (discordid) DO UPDATE SET boards = EXCLUDED.boards",
+        &[&i64::from(ctx.author().id), &boards],
+    ).await?;
+    match boards {
+        Some(boards) => reply(&ctx, format!("You will be notified about: {}", boards.join(", "))).await?,
+        None => reply(&ctx, "You will be notified about all leaderboards.".to_string()).await?,
+    }
+    Ok(())
+}
+
+/// Set hours (UTC) during which you don't want to be notified
+#[poise::command(slash_command, rename = "quiet")]
+pub async fn notifications_quiet(
+    ctx: Context<'_>,
+    #[description = "First quiet hour (UTC), empty to disable quiet hours"] start: Option<i16>,
+    #[description = "Hour (UTC) at which notifications resume"] end: Option<i16>,
+) -> Result<(), Error> {
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) if (0..24).contains(&start) && (0..24).contains(&end) => (Some(start), Some(end)),
+        (None, None) => (None, None),
+        _ => {
+            reply(&ctx, "Give both a start and an end hour between 0 and 23.".to_string()).await?;
+            return Ok(())
+        }
+    };
+    ctx.data().database.execute(
+        "INSERT INTO notification_settings (discordid, quiet_start, quiet_end) VALUES ($1, $2, $3)
+        ON CONFLICT (discordid) DO UPDATE SET quiet_start = EXCLUDED.quiet_start, quiet_end = EXCLUDED.quiet_end",
+        &[&i64::from(ctx.author().id), &start, &end],
+    ).await?;
+    match (start, end) {
+        (Some(start), Some(end)) => reply(&ctx, format!("No notifications between {}:00 and {}:00 UTC.", start, end)).await?,
+        _ => reply(&ctx, "Quiet hours disabled.".to_string()).await?,
+    }
+    Ok(())
+}
+
+/// Show your notification settings
+#[poise::command(slash_command, rename = "show")]
+pub async fn notifications_show(ctx: Context<'_>) -> Result<(), Error> {
+    let settings = NotificationSettings::fetch(&ctx.data().database, i64::from(ctx.author().id)).await?;
+    let boards = match &settings.boards {
+        Some(boards) => boards.join(", "),
+        None => "all".to_string(),
+    };
+    let quiet = match settings.quiet_hours {
+        Some((start, end)) => format!("{}:00 - {}:00 UTC", start, end),
+        None => "none".to_string(),
+    };
+    reply(&ctx, format!(
+        "Notifications: {}\nBoards: {}\nQuiet hours: {}",
+        if settings.enabled { "on" } else { "off" }, boards, quiet,
+    )).await?;
+    Ok(())
+}
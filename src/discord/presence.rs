@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use tokio::time::{sleep, Duration, Instant};
+
+use poise::serenity_prelude as ser;
+use crate::metrics;
+use crate::Data;
+use crate::status::format_summary;
+
+// Discord allows two channel renames per ten minutes, keep a safe distance
+const RENAME_INTERVAL: Duration = Duration::from_secs(310);
+
+// Names we last gave each channel and when
+type Renames = HashMap<u64, (String, Instant)>;
+
+pub async fn presence_loop(ctx: ser::Context, data: Data) {
+    let mut renames: Renames = HashMap::new();
+    let mut shutdown = data.shutdown.clone();
+    loop {
+        update_presence(&ctx, &data, &mut renames).await;
+        tokio::select! {
+            _ = sleep(Duration::from_secs(data.config.get().status.update_interval)) => {},
+            _ = shutdown.wait() => break,
+        }
+    }
+}
+
+async fn update_presence(ctx: &ser::Context, data: &Data, renames: &mut Renames) {
+    let config = data.config.get();
+    let config = &config.status;
+    let servers = data.servers.online();
+
+    if config.presence {
+        let activity = format_summary(&config.presence_format, &servers);
+        ctx.set_activity(Some(ser::ActivityData::custom(activity)));
+    }
+
+    for status_channel in config.channels.iter() {
+        let name = match &status_channel.server {
+            Some(server) => {
+                let server: Vec<_> = servers.iter().filter(|status| &status.name == server).cloned().collect();
+                format_summary(&status_channel.format, &server)
+            },
+            None => format_summary(&status_channel.format, &servers),
+        };
+
+        if let Some((last_name, renamed_at)) = renames.get(&status_channel.channel) {
+            if last_name == &name || renamed_at.elapsed() < RENAME_INTERVAL {
+                continue;
+            }
+        }
+
+        let channel = ser::ChannelId::new(status_channel.channel);
+        match channel.edit(&ctx.http, ser::EditChannel::default().name(name.clone())).await {
+            Ok(_) => {
+                log::debug!("Renamed channel {} to {}", channel, name);
+            },
+            Err(e) => {
+                log::error!("Failed to rename channel {}: {}", channel, e);
+                metrics::discord_error("presence");
+            }
+        }
+        // Failed renames count too, so a rate limited channel isn't retried every interval
+        renames.insert(status_channel.channel, (name, Instant::now()));
+    }
+}
@@ -0,0 +1,77 @@
+use poise::serenity_prelude as ser;
+use crate::metrics;
+use poise::reply;
+
+use crate::{Context, Data, Error};
+use crate::status::ServerStatus;
+
+pub fn status_embed(servers: &[ServerStatus]) -> ser::CreateEmbed {
+    let mut embed = ser::CreateEmbed::default()
+        .title("swim> servers")
+        .color(ser::Colour::from_rgb(255, 255, 255));
+
+    if servers.is_empty() {
+        return embed.description("No servers online.");
+    }
+
+    let players: usize = servers.iter().map(|server| server.players.len()).sum();
+    embed = embed.description(format!("{} drivers on {} servers", players, servers.len()));
+
+    for server in servers {
+        let mut drivers: Vec<&str> = server.players.iter().map(|player| player.name.as_str()).collect();
+        drivers.sort_unstable();
+        let mut value = format!("{} - {}/{}", server.track, server.players.len(), server.capacity);
+        if !drivers.is_empty() {
+            value.push_str(&format!("\n{}", drivers.join(", ")));
+        }
+        embed = embed.field(server.name.clone(), value, false);
+    }
+    embed
+}
+
+// Keeps the status panel in the configured channel up to date
+pub async fn update_status_panel(ctx: &ser::Context, data: &Data) {
+    let channel_id = match data.config.get().discord.status_channel {
+        Some(channel_id) => ser::ChannelId::new(channel_id),
+        None => return,
+    };
+    let http = &ctx.http;
+    let embed = status_embed(&data.servers.online());
+
+    let mut messages = match channel_id.messages(http, ser::GetMessages::default().limit(1)).await {
+        Ok(messages) => messages,
+        Err(e) => {
+            log::error!("Failed to fetch messages from channel {}: {}", channel_id, e);
+            metrics::discord_error("status");
+            return;
+        }
+    };
+
+    let bot_id = ctx.cache.current_user().id;
+    match messages.first_mut() {
+        Some(message) if message.author.id == bot_id => {
+            let msg = ser::EditMessage::default()
+                .embeds(vec![embed]);
+            if let Err(e) = message.edit(http, msg).await {
+                log::error!("Failed to edit status panel: {}", e);
+                metrics::discord_error("status");
+            }
+        },
+        _ => {
+            let msg = ser::CreateMessage::default()
+                .embeds(vec![embed]);
+            if let Err(e) = channel_id.send_message(http, msg).await {
+                log::error!("Failed to send status panel: {}", e);
+                metrics::discord_error("status");
+            }
+        }
+    }
+}
+
+/// Shows which servers are online and who is driving
+#[poise::command(slash_command, prefix_command)]
+pub async fn servers(ctx: Context<'_>) -> Result<(), Error> {
+    let embed = status_embed(&ctx.data().servers.online());
+    ctx.send(reply::CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use crate::{Context, Error};
+use crate::webhooks::{self, EVENTS};
+use super::admin::owner_check;
+use super::commands::reply;
+use super::moderation::timestamp;
+
+// Dead letters shown by /webhook failures
+const FAILURES_SHOWN: i64 = 10;
+
+/// Outbound webhooks
+#[poise::command(slash_command, hide_in_help, check = "owner_check",
+    subcommands("webhook_add", "webhook_test", "webhook_remove", "webhook_list", "webhook_failures"))]
+pub async fn webhook(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Sends bot events to a URL, the signing secret is only shown once
+#[poise::command(slash_command, rename = "add", check = "owner_check")]
+pub async fn webhook_add(
+    ctx: Context<'_>,
+    #[description = "URL the events are posted to"] url: String,
+    #[description = "Comma separated events, e.g. highscore,record,ban"] events: String,
+) -> Result<(), Error> {
+    match reqwest::Url::parse(&url) {
+        Ok(parsed) if parsed.scheme() == "https" || parsed.scheme() == "http" => {},
+        _ => {
+            reply(&ctx, format!("{} is not a valid http(s) URL.", url)).await?;
+            return Ok(())
+        },
+    }
+    let events: Vec<String> = events.split(',').map(|event| event.trim().to_lowercase()).filter(|event| !event.is_empty()).collect();
+    if events.is_empty() {
+        reply(&ctx, format!("Name at least one event: {}", EVENTS.join(", "))).await?;
+        return Ok(())
+    }
+    if let Some(unknown) = events.iter().find(|event| !EVENTS.contains(&event.as_str())) {
+        reply(&ctx, format!("Unknown event {}, choose from: {}", unknown, EVENTS.join(", "))).await?;
+        return Ok(())
+    }
+
+    let data = ctx.data();
+    let webhook = data.database.insert_webhook(&url, &events, &webhooks::generate_secret(), i64::from(ctx.author().id)).await?;
+    data.webhooks.invalidate().await;
+    log::info!("{} added webhook {} for {}", ctx.author().name, webhook.id, webhook.events.join(","));
+    reply(&ctx, format!(
+        "Added webhook #{} for {}.\nSigning secret, it won't be shown again: `{}`",
+        webhook.id, webhook.events.join(", "), webhook.secret,
+    )).await?;
+    Ok(())
+}
+
+/// Sends a test event to a webhook
+#[poise::command(slash_command, rename = "test", check = "owner_check")]
+pub async fn webhook_test(ctx: Context<'_>, #[description = "Id of the webhook"] id: i32) -> Result<(), Error> {
+    ctx.defer_ephemeral().await?;
+    let data = ctx.data();
+    let webhook = match data.database.webhooks().await?.into_iter().find(|webhook| webhook.id == id) {
+        Some(webhook) => webhook,
+        None => {
+            reply(&ctx, format!("There's no webhook #{}.", id)).await?;
+            return Ok(())
+        },
+    };
+    match data.webhooks.test(&webhook).await {
+        Ok(()) => reply(&ctx, format!("Delivered a test event to webhook #{}.", id)).await?,
+        Err(e) => reply(&ctx, format!("Test delivery to webhook #{} failed: {}", id, e)).await?,
+    }
+    Ok(())
+}
+
+/// Removes a webhook and its failed deliveries
+#[poise::command(slash_command, rename = "remove", check = "owner_check")]
+pub async fn webhook_remove(ctx: Context<'_>, #[description = "Id of the webhook"] id: i32) -> Result<(), Error> {
+    let data = ctx.data();
+    if data.database.delete_webhook(id).await? {
+        data.webhooks.invalidate().await;
+        log::info!("{} removed webhook {}", ctx.author().name, id);
+        reply(&ctx, format!("Removed webhook #{}.", id)).await?;
+    } else {
+        reply(&ctx, format!("There's no webhook #{}.", id)).await?;
+    }
+    Ok(())
+}
+
+/// Lists the webhooks
+#[poise::command(slash_command, rename = "list", check = "owner_check")]
+pub async fn webhook_list(ctx: Context<'_>) -> Result<(), Error> {
+    let webhooks = ctx.data().database.webhooks().await?;
+    if webhooks.is_empty() {
+        reply(&ctx, "There are no webhooks.".to_string()).await?;
+        return Ok(())
+    }
+    let lines: Vec<String> = webhooks.iter()
+        .map(|webhook| format!("#{} {} ({}) added by <@{}>", webhook.id, webhook.url, webhook.events.join(", "), webhook.created_by))
+        .collect();
+    reply(&ctx, lines.join("\n")).await?;
+    Ok(())
+}
+
+/// Shows the latest deliveries which failed every attempt
+#[poise::command(slash_command, rename = "failures", check = "owner_check")]
+pub async fn webhook_failures(ctx: Context<'_>, #[description = "Only of this webhook"] id: Option<i32>) -> Result<(), Error> {
+    let dead_letters = ctx.data().database.dead_letters(id, FAILURES_SHOWN).await?;
+    if dead_letters.is_empty() {
+        reply(&ctx, "No failed deliveries.".to_string()).await?;
+        return Ok(())
+    }
+    let lines: Vec<String> = dead_letters.iter()
+        .map(|letter| format!(
+            "[{}] webhook #{} {} after {} attempt(s) {}: {}",
+            letter.id, letter.webhook_id, letter.event, letter.attempts, timestamp(letter.failed_at), letter.error,
+        ))
+        .collect();
+    reply(&ctx, lines.join("\n")).await?;
+    Ok(())
+}
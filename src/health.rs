@@ -0,0 +1,27 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Progress markers of background work, checked by the readiness endpoint
+#[derive(Debug, Default)]
+pub struct Health {
+    // Unix time of the last finished leaderboard update, 0 if there was none yet
+    leaderboards_updated: AtomicU64,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl Health {
+    pub fn leaderboards_updated(&self) {
+        self.leaderboards_updated.store(now(), Ordering::Relaxed);
+    }
+
+    // Time since the last leaderboard update, None if there was none yet
+    pub fn leaderboards_age(&self) -> Option<Duration> {
+        match self.leaderboards_updated.load(Ordering::Relaxed) {
+            0 => None,
+            updated => Some(Duration::from_secs(now().saturating_sub(updated))),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,232 @@
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+use log::kv::{self, Source, VisitSource};
+use log::{LevelFilter, Metadata};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::config::{parse_level_filter, Log};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> LogFormat {
+        LogFormat::Text
+    }
+}
+
+tokio::task_local! {
+    // Fields added to every line logged by the task, e.g. the steamid a request is about
+    static CONTEXT: Vec<(&'static str, String)>;
+}
+
+// Runs the future with extra fields on its log lines, nested scopes keep the outer fields
+pub async fn scope<F: Future>(fields: Vec<(&'static str, String)>, future: F) -> F::Output {
+    let mut context = current();
+    context.extend(fields);
+    CONTEXT.scope(context, future).await
+}
+
+// The fields of the current task, to carry them over into spawned tasks
+pub fn current() -> Vec<(&'static str, String)> {
+    CONTEXT.try_with(|context| context.clone()).unwrap_or_default()
+}
+
+pub fn field(key: &str) -> Option<String> {
+    CONTEXT.try_with(|context| context.iter().find(|(k, _)| *k == key).map(|(_, value)| value.clone())).ok().flatten()
+}
+
+#[derive(Debug)]
+struct Levels {
+    default: LevelFilter,
+    // Sorted by length so the most specific module wins
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Levels {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules.iter()
+            .find(|(module, _)| target == module || target.starts_with(&format!("{}::", module)))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+}
+
+// Levels are kept outside of fern so they can be changed by a config reload
+static LEVELS: Lazy<RwLock<Levels>> = Lazy::new(|| RwLock::new(Levels {
+    default: LevelFilter::Info,
+    modules: Vec::new(),
+}));
+
+// Applies the global level and the per-module overrides of the config
+pub fn set_levels(config: &Log) {
+    let mut modules: Vec<(String, LevelFilter)> = config.modules.iter()
+        .map(|(module, level)| (module.clone(), parse_level_filter(level).unwrap_or(LevelFilter::Info)))
+        .collect();
+    modules.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+
+    let default = config.level_filter();
+    // The log crate drops everything above the max level before fern sees it
+    let max = modules.iter().map(|(_, level)| *level).fold(default, std::cmp::max);
+    *LEVELS.write().unwrap() = Levels { default, modules };
+    log::set_max_level(max);
+}
+
+fn enabled(metadata: &Metadata) -> bool {
+    metadata.level() <= LEVELS.read().unwrap().level_for(metadata.target())
+}
+
+// Collects the key-values given to a log macro, e.g. `log::info!(steamid = id; "...")`
+struct Fields(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+fn fields(record: &log::Record) -> Vec<(String, String)> {
+    let mut fields = Fields(CONTEXT
+        .try_with(|context| context.iter().map(|(key, value)| (key.to_string(), value.clone())).collect())
+        .unwrap_or_default());
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+pub fn setup(config: &Log) -> Result<(), crate::Error> {
+    let format = config.format;
+    let mut dispatch = fern::Dispatch::new()
+        .format(move |out, message, record| {
+            let time = humantime::format_rfc3339(SystemTime::now());
+            let fields = fields(record);
+            match format {
+                LogFormat::Text => {
+                    let fields: String = fields.iter().map(|(key, value)| format!(" {}={}", key, value)).collect();
+                    out.finish(format_args!("[{} {} {}] {}{}", time, record.level(), record.target(), message, fields))
+                },
+                LogFormat::Json => {
+                    let mut line = serde_json::Map::new();
+                    line.insert("time".to_string(), time.to_string().into());
+                    line.insert("level".to_string(), record.level().as_str().into());
+                    line.insert("target".to_string(), record.target().into());
+                    line.insert("message".to_string(), message.to_string().into());
+                    for (key, value) in fields {
+                        line.entry(key).or_insert(value.into());
+                    }
+                    out.finish(format_args!("{}", serde_json::Value::Object(line)))
+                },
+            }
+        })
+        .filter(enabled)
+        .level_for("tracing::span", LevelFilter::Off)
+        .level_for("serenity::gateway::shard", LevelFilter::Off);
+
+    if config.stdout {
+        dispatch = dispatch.chain(std::io::stdout());
+    }
+    let file = RotatingFile::open(config)
+        .map_err(|e| format!("Failed to open the log file {}: {}", config.file_output, e))?;
+    dispatch
+        .chain(Box::new(file) as Box<dyn Write + Send>)
+        .apply()?;
+    set_levels(config);
+
+    log::debug!("Setup logging...");
+    Ok(())
+}
+
+// Log file which is moved to `<path>.1`, `<path>.2`, ... once it grows too big or too old
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    interval: Option<Duration>,
+    keep: usize,
+    file: File,
+    size: u64,
+    // When this process opened or last rotated the file, the interval counts from there
+    opened: SystemTime,
+}
+
+impl RotatingFile {
+    fn open(config: &Log) -> io::Result<RotatingFile> {
+        let path = PathBuf::from(&config.file_output);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        Ok(RotatingFile {
+            path,
+            max_size: config.max_size_mb * 1024 * 1024,
+            interval: match config.rotate_interval {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            keep: config.keep,
+            size: metadata.len(),
+            // Creation times aren't available on every filesystem, a restart starts a new interval instead
+            opened: SystemTime::now(),
+            file,
+        })
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let too_big = self.max_size > 0 && self.size >= self.max_size;
+        let too_old = match self.interval {
+            Some(interval) => self.opened.elapsed().map(|age| age >= interval).unwrap_or(false),
+            None => false,
+        };
+        too_big || too_old
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.keep));
+            for index in (1..self.keep).rev() {
+                let _ = fs::rename(self.rotated(index), self.rotated(index + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        self.opened = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation() {
+            if let Err(e) = self.rotate() {
+                // Logging can't report its own failure, keep writing to the current file
+                eprintln!("Failed to rotate {}: {}", self.path.display(), e);
+                self.opened = SystemTime::now();
+                self.size = 0;
+            }
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -2,13 +2,18 @@ mod discord;
 mod api;
 mod database;
 mod config;
+mod status;
+mod cli;
+mod shutdown;
+mod metrics;
+mod health;
+mod logging;
+mod bus;
+mod webhooks;
 
 use std::sync::Arc;
-use std::fs;
 use std::process::exit;
-use fern;
-use log::LevelFilter;
-use toml;
+use clap::Parser;
 
 use serde::Deserialize;
 
@@ -17,117 +22,274 @@ use poise::serenity_prelude as serenity;
 #[derive(Debug, Clone)]
 pub struct Data {
     pub database: Arc<database::Database>,
-    pub config: Config,
+    pub servers: Arc<status::ServerStatuses>,
+    pub config: Arc<config::SharedConfig>,
+    pub shutdown: shutdown::Shutdown,
+    pub health: Arc<health::Health>,
+    pub bus: Arc<bus::EventBus>,
+    pub webhooks: Arc<webhooks::Dispatcher>,
 }
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Context<'a> = poise::Context<'a, Data, Error>;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
+    // Seconds to wait for running work to finish on shutdown
+    #[serde(default = "config::default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
     pub log: config::Log,
     pub discord: discord::config::DiscordConfig,
     pub api: api::config::APIConfig,
     pub database: database::config::DatabaseConfig,
+    #[serde(default)]
+    pub status: status::config::StatusConfig,
+    #[serde(default)]
+    pub webhooks: webhooks::config::WebhookConfig,
 }
 
 #[tokio::main]
 async fn main() {
+    let cli = cli::Cli::parse();
+
     // Read the config file
-    let contents = match fs::read_to_string("config.toml") {
+    let config = match config::load(&cli.config) {
         Ok(config) => config,
         Err(e) => {
-            println!("Failed to read config file: {}", e);
+            eprintln!("{}", e);
             exit(1);
         }
     };
 
-    let config: Config = match toml::from_str(&contents) {
-        // If successful, return data as `Data` struct.
-        // `d` is a local variable.
-        Ok(d) => d,
-        // Handle the `error` case.
+    let result = match cli.command.unwrap_or(cli::Command::Run(cli::RunArgs::default())) {
+        cli::Command::Run(args) => {
+            if !report_issues(&config) {
+                exit(1);
+            }
+            setup_logging(&config);
+            run(Arc::new(config::SharedConfig::new(cli.config, config)), args).await;
+            Ok(())
+        },
+        cli::Command::CheckConfig => {
+            if !report_issues(&config) {
+                exit(1);
+            }
+            println!("Config {} is valid", cli.config.display());
+            Ok(())
+        },
+        cli::Command::Migrate => {
+            setup_logging(&config);
+            cli::migrate(&config).await
+        },
+        cli::Command::RegisterCommands { global } => {
+            setup_logging(&config);
+            cli::register_commands(&config, global).await
+        },
+        cli::Command::Export { table, output } => cli::export(&config, table, output).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+// Nothing could be reported without logging, so failing to set it up ends the process
+fn setup_logging(config: &Config) {
+    if let Err(e) = logging::setup(&config.log) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+// Prints every problem with the config, returns whether it is valid
+fn report_issues(config: &Config) -> bool {
+    let issues = config::validate(config);
+    if issues.is_empty() {
+        return true;
+    }
+    eprintln!("Found {} problem(s) in the config:", issues.len());
+    for issue in issues {
+        eprintln!("  {}", issue);
+    }
+    false
+}
+
+async fn run(shared_config: Arc<config::SharedConfig>, args: cli::RunArgs) {
+    let config = shared_config.get();
+    let (mut shutdown_trigger, shutdown) = shutdown::channel();
+
+    let database = match database::Database::new(config.database.clone()).await {
+        Ok(database) => Arc::new(database),
         Err(e) => {
-            // Write `msg` to `stderr`.
-            eprintln!("Unable to load data from config.toml `{}`", e);
-            // Exit the program with exit code `1`.
+            log::error!("Failed to connect to the database: {}", e);
             exit(1);
         }
     };
-
-    // Setup logging
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{} {} {}] {}",
-                humantime::format_rfc3339(std::time::SystemTime::now()),
-                record.level(),
-                record.target(),
-                message
-            ))
-        })
-        .level(config.log.level)
-        .level_for("tracing::span", LevelFilter::Off)
-        .level_for("serenity::gateway::shard", LevelFilter::Off)
-        .chain(if config.log.stdout {
-            Box::new(std::io::stdout()) as Box<dyn std::io::Write + Send>
-        } else {
-            Box::new(std::io::sink()) // If stdout is false, don't log to stdout
-        })
-        .chain(fern::log_file(&config.log.file_output).unwrap())
-        .apply().unwrap();
-
-    log::debug!("Setup logging...");
+    let database_closed = database.closed();
 
     let _data = Arc::new(Data {
-        database: Arc::new(database::Database::new(config.database.clone()).await.unwrap()),
-        config: config.clone(),
+        database: database.clone(),
+        servers: Arc::new(status::ServerStatuses::new(std::time::Duration::from_secs(config.api.heartbeat_expiry))),
+        config: shared_config.clone(),
+        shutdown: shutdown.clone(),
+        health: Arc::new(health::Health::default()),
+        bus: Arc::new(bus::EventBus::new()),
+        webhooks: Arc::new(webhooks::Dispatcher::new(database.clone(), shared_config.clone())),
     });
 
-    let options = poise::FrameworkOptions {
-        prefix_options: poise::PrefixFrameworkOptions {
-            prefix: Some(config.discord.prefix.clone().into()),
-            // edit_tracker: Some(poise::EditTracker::for_timespan(std::time::Duration::from_secs(config.discord.edit_track_timespan))),
-            ..Default::default()
-        },
-        commands: vec![discord::commands::register(), discord::commands::help(), discord::commands::ping(), discord::commands::link(), discord::commands::steamid(), discord::commands::score()],
-        event_handler: |ctx, event| { // Modified the closure to take only two arguments
-            Box::pin(discord::event_handler::event_handler(ctx, event)) // Removed the unnecessary arguments
-        },
-        pre_command: |ctx| Box::pin(async move {
-            log::debug!("Executing command {}...", ctx.command().qualified_name);
-        }),
-        post_command: |ctx| Box::pin(async move {
-            log::info!("Executed command {}!", ctx.command().qualified_name);
-        }),
-        ..Default::default()
-    };
-
-    let mut client = serenity::ClientBuilder::new(&config.discord.token, serenity::GatewayIntents::all())
-        .framework(poise::Framework::new(options))
-        .data(_data.clone())
-        .await
-        .expect("Failed to create client");
-
-    let (http, cache) = (client.http.clone(), client.cache.clone());
+    #[cfg(unix)]
+    tokio::spawn(reload_on_sighup(shared_config.clone()));
+    tokio::spawn(database::events::prune_loop(_data.database.clone(), shared_config.clone(), shutdown.clone()));
+    tokio::spawn(_data.webhooks.clone().run(_data.bus.clone(), shutdown.clone()));
 
-    let app_state = api::models::AppState {
+    let app_state = |http: Arc<serenity::Http>, cache: Arc<serenity::Cache>, shard_manager: Option<Arc<serenity::ShardManager>>| api::models::AppState {
         http,
         cache,
+        shard_manager,
+        health: _data.health.clone(),
         database: _data.database.clone(),
-        config: config.clone(),
+        servers: _data.servers.clone(),
+        announcer: Arc::new(discord::announcements::Announcer::new()),
+        rate_limiter: Arc::new(api::ratelimit::RateLimiter::new(_data.database.clone())),
+        bus: _data.bus.clone(),
+        config: shared_config.clone(),
+        shutdown: _data.shutdown.clone(),
     };
 
-    let routes = api::combined_routes(app_state); // Assuming app_state contains the necessary shared state
+    // Stops accepting connections once the shutdown is triggered and finishes the running requests
+    let serve_api = |state: api::models::AppState| {
+        let mut shutdown = shutdown.clone();
+        let (address, server) = api::server::serve(state, config.api.address().expect("Invalid address"), async move {
+            shutdown.wait().await;
+        });
+        log::info!("Started web API on {}", address);
+        tokio::spawn(server)
+    };
 
-    // Start web API
-    tokio::spawn(async move {
-        warp::serve(routes).run(config.api.address()).await;
-    });
-    log::debug!("Started web API!");
-    log::debug!("Discord, database and api setup complete!");
-        
-    // Let's run
-    log::info!("Starting bot...");
-    client.start().await.unwrap();
+    let mut api_task = None;
+    let mut bot_task = None;
+    let mut shard_manager = None;
+    // Lets us shut down if the discord client stops on its own
+    let (bot_stopped_sender, mut bot_stopped) = tokio::sync::oneshot::channel::<()>();
+    let mut bot_stopped_sender = Some(bot_stopped_sender);
+
+    if args.api_only || args.bot_only {
+        log::warn!("Running only the {}, the event feed and webhooks only see events of this process",
+            if args.api_only { "API" } else { "bot" });
+    }
+    if args.bot_only {
+        tokio::spawn(status::sync_loop(_data.database.clone(), _data.servers.clone(), shared_config.clone(), shutdown.clone()));
+    }
+
+    if args.api_only {
+        // Without a gateway connection the API talks to discord over HTTP only
+        let http = Arc::new(serenity::Http::new(&config.discord.token));
+        api_task = Some(serve_api(app_state(http, Arc::new(serenity::Cache::new()), None)));
+    } else {
+        let options = poise::FrameworkOptions {
+            prefix_options: poise::PrefixFrameworkOptions {
+                prefix: Some(config.discord.prefix.clone().into()),
+                // edit_tracker: Some(poise::EditTracker::for_timespan(std::time::Duration::from_secs(config.discord.edit_track_timespan))),
+                ..Default::default()
+            },
+            commands: discord::commands::all(),
+            event_handler: |ctx, event| { // Modified the closure to take only two arguments
+                Box::pin(discord::event_handler::event_handler(ctx, event)) // Removed the unnecessary arguments
+            },
+            pre_command: |ctx| Box::pin(async move {
+                log::debug!(
+                    command = ctx.command().qualified_name.as_str(),
+                    user = ctx.author().id.get(),
+                    guild = ctx.guild_id().map(|guild| guild.get()).unwrap_or(0);
+                    "Executing command {}...", ctx.command().qualified_name
+                );
+                metrics::COMMANDS.with_label_values(&[&ctx.command().qualified_name]).inc();
+            }),
+            post_command: |ctx| Box::pin(async move {
+                log::info!(
+                    command = ctx.command().qualified_name.as_str(),
+                    user = ctx.author().id.get(),
+                    guild = ctx.guild_id().map(|guild| guild.get()).unwrap_or(0);
+                    "Executed command {}!", ctx.command().qualified_name
+                );
+            }),
+            ..Default::default()
+        };
+
+        let mut client = serenity::ClientBuilder::new(&config.discord.token, serenity::GatewayIntents::all())
+            .framework(poise::Framework::new(options))
+            .data(_data.clone())
+            .await
+            .expect("Failed to create client");
+
+        if !args.bot_only {
+            api_task = Some(serve_api(app_state(client.http.clone(), client.cache.clone(), Some(client.shard_manager.clone()))));
+        }
+        log::debug!("Discord, database and api setup complete!");
+
+        // Let's run
+        log::info!("Starting bot...");
+        shard_manager = Some(client.shard_manager.clone());
+        let bot_stopped_sender = bot_stopped_sender.take();
+        bot_task = Some(tokio::spawn(async move {
+            if let Err(e) = client.start().await {
+                log::error!("Discord client stopped: {}", e);
+            }
+            if let Some(sender) = bot_stopped_sender {
+                let _ = sender.send(());
+            }
+        }));
+    }
+
+    tokio::select! {
+        _ = shutdown::signal() => {},
+        _ = &mut bot_stopped => log::error!("Discord client stopped unexpectedly"),
+    }
+    log::info!("Shutting down...");
+    shutdown_trigger.trigger();
+    if let Some(shard_manager) = shard_manager {
+        shard_manager.shutdown_all().await;
+    }
+    // Drop our own handles, the remaining ones belong to tasks which are still finishing up
+    drop(app_state);
+    drop(serve_api);
+    drop(_data);
+    drop(shutdown);
+    drop(bot_stopped_sender);
 
+    let timeout = std::time::Duration::from_secs(config.shutdown_timeout);
+    let finished = tokio::time::timeout(timeout, async move {
+        if let Some(api_task) = api_task {
+            let _ = api_task.await;
+        }
+        if let Some(bot_task) = bot_task {
+            let _ = bot_task.await;
+        }
+        shutdown_trigger.finished().await;
+        database_closed.await;
+    }).await;
+
+    match finished {
+        Ok(_) => log::info!("Shut down cleanly"),
+        Err(_) => log::warn!("Shutdown timed out after {} seconds, exiting anyway", config.shutdown_timeout),
+    }
+}
+
+#[cfg(unix)]
+async fn reload_on_sighup(config: Arc<config::SharedConfig>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            log::error!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        log::info!("Received SIGHUP, reloading config...");
+        if let Err(e) = config.reload() {
+            log::error!("Failed to reload config: {}", e);
+        }
+    }
 }
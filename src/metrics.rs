@@ -0,0 +1,65 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, TextEncoder,
+};
+
+// All metrics live in the default prometheus registry and are exported by the /metrics route
+
+pub static API_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| register_int_counter_vec!(
+    "swimbot_api_requests_total", "API requests by route and response status", &["route", "status"]
+).unwrap());
+
+pub static API_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| register_histogram_vec!(
+    "swimbot_api_request_duration_seconds", "API request duration by route", &["route"]
+).unwrap());
+
+pub static LEGACY_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| register_int_counter_vec!(
+    "swimbot_api_legacy_requests_total", "Requests to the unversioned legacy routes", &["route"]
+).unwrap());
+
+pub static DB_QUERY_DURATION: Lazy<HistogramVec> = Lazy::new(|| register_histogram_vec!(
+    "swimbot_db_query_duration_seconds", "Database query latency by operation", &["operation"]
+).unwrap());
+
+pub static SCORE_INSERTS: Lazy<IntCounterVec> = Lazy::new(|| register_int_counter_vec!(
+    "swimbot_score_inserts_total", "Submitted cutup scores by outcome (improved, not_improved, rejected, banned, error)", &["result"]
+).unwrap());
+
+pub static DUPLICATE_EVENTS: Lazy<IntCounter> = Lazy::new(|| register_int_counter!(
+    "swimbot_duplicate_events_total", "Submissions skipped because their event id was already processed"
+).unwrap());
+
+pub static WHITELIST_CHECKS: Lazy<IntCounterVec> = Lazy::new(|| register_int_counter_vec!(
+    "swimbot_whitelist_checks_total", "Whitelist checks by policy and outcome (allowed, denied, error)", &["policy", "result"]
+).unwrap());
+
+pub static COMMANDS: Lazy<IntCounterVec> = Lazy::new(|| register_int_counter_vec!(
+    "swimbot_command_invocations_total", "Discord command invocations by command", &["command"]
+).unwrap());
+
+pub static LEADERBOARD_UPDATE_DURATION: Lazy<Histogram> = Lazy::new(|| register_histogram!(
+    "swimbot_leaderboard_update_duration_seconds", "Duration of a full leaderboard update",
+    vec![0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]
+).unwrap());
+
+pub static DISCORD_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| register_int_counter_vec!(
+    "swimbot_discord_errors_total", "Failed Discord API calls by the part of the bot making them", &["source"]
+).unwrap());
+
+pub static WEBHOOK_DELIVERIES: Lazy<IntCounterVec> = Lazy::new(|| register_int_counter_vec!(
+    "swimbot_webhook_deliveries_total", "Webhook delivery attempts by outcome (delivered, retried, failed)", &["result"]
+).unwrap());
+
+pub fn discord_error(source: &str) {
+    DISCORD_ERRORS.with_label_values(&[source]).inc();
+}
+
+// Renders every metric in the prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        log::error!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
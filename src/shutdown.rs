@@ -0,0 +1,76 @@
+use tokio::sync::{mpsc, watch};
+
+// Handle given to long running tasks. It tells them when to stop, and every clone
+// keeps the shutdown waiting until it is dropped, so tasks get to finish what they are doing.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    notify: watch::Receiver<bool>,
+    _running: mpsc::Sender<()>,
+}
+
+#[derive(Debug)]
+pub struct ShutdownTrigger {
+    notify: watch::Sender<bool>,
+    running: mpsc::Receiver<()>,
+}
+
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (notify_sender, notify_receiver) = watch::channel(false);
+    let (running_sender, running_receiver) = mpsc::channel(1);
+    (
+        ShutdownTrigger { notify: notify_sender, running: running_receiver },
+        Shutdown { notify: notify_receiver, _running: running_sender },
+    )
+}
+
+impl Shutdown {
+    pub fn is_shutting_down(&self) -> bool {
+        *self.notify.borrow()
+    }
+
+    // Resolves once the shutdown was triggered
+    pub async fn wait(&mut self) {
+        while !*self.notify.borrow() {
+            if self.notify.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        let _ = self.notify.send(true);
+    }
+
+    // Resolves once every Shutdown handle was dropped
+    pub async fn finished(&mut self) {
+        let _ = self.running.recv().await;
+    }
+}
+
+// Resolves on SIGTERM (docker stop) or ctrl-c
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = match signal(SignalKind::terminate()) {
+            Ok(terminate) => terminate,
+            Err(e) => {
+                log::error!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
+        tokio::select! {
+            _ = terminate.recv() => log::info!("Received SIGTERM"),
+            _ = tokio::signal::ctrl_c() => log::info!("Received ctrl-c"),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        log::info!("Received ctrl-c");
+    }
+}
@@ -0,0 +1,59 @@
+use serde::Deserialize;
+
+use crate::config::Issues;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusConfig {
+    // Show the player count as the bot's activity
+    #[serde(default = "default_presence")]
+    pub presence: bool,
+    #[serde(default = "default_presence_format")]
+    pub presence_format: String,
+    // Seconds between presence and channel name updates
+    #[serde(default = "default_update_interval")]
+    pub update_interval: u64,
+    #[serde(default)]
+    pub channels: Vec<StatusChannel>,
+}
+
+// A (voice) channel whose name shows the player count of all servers or of a single one.
+// Supported placeholders: {players}, {capacity}, {servers} and {track}.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatusChannel {
+    pub channel: u64,
+    pub format: String,
+    pub server: Option<String>,
+}
+
+fn default_presence() -> bool {
+    true
+}
+
+fn default_presence_format() -> String {
+    "{players} drivers on {servers} servers".to_string()
+}
+
+fn default_update_interval() -> u64 {
+    60
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        StatusConfig {
+            presence: default_presence(),
+            presence_format: default_presence_format(),
+            update_interval: default_update_interval(),
+            channels: Vec::new(),
+        }
+    }
+}
+
+impl StatusConfig {
+    pub fn validate(&self, issues: &mut Issues) {
+        issues.check(self.update_interval > 0, "status.update_interval", "must be at least 1 second");
+        for (i, channel) in self.channels.iter().enumerate() {
+            issues.check_id(channel.channel, format!("status.channels[{}].channel", i));
+            issues.check(!channel.format.is_empty(), format!("status.channels[{}].format", i), "must not be empty");
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,103 @@
+pub mod config;
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use crate::database::Database;
+use crate::shutdown::Shutdown;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Player {
+    pub name: String,
+    pub steamid: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerStatus {
+    pub name: String,
+    pub track: String,
+    pub players: Vec<Player>,
+    pub capacity: u32,
+}
+
+// In-memory view of the game servers, fed by their heartbeats.
+// Servers which didn't send a heartbeat within `expiry` are considered offline.
+#[derive(Debug)]
+pub struct ServerStatuses {
+    expiry: Duration,
+    servers: RwLock<HashMap<String, (ServerStatus, Instant)>>,
+}
+
+impl ServerStatuses {
+    pub fn new(expiry: Duration) -> ServerStatuses {
+        ServerStatuses {
+            expiry,
+            servers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn update(&self, status: ServerStatus) {
+        let mut servers = self.servers.write().unwrap();
+        servers.insert(status.name.clone(), (status, Instant::now()));
+    }
+
+    // Takes over a heartbeat another process received `age` ago
+    pub fn restore(&self, status: ServerStatus, age: Duration) {
+        let seen = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+        let mut servers = self.servers.write().unwrap();
+        servers.insert(status.name.clone(), (status, seen));
+    }
+
+    // Returns the servers which are still alive sorted by name, forgetting expired ones
+    pub fn online(&self) -> Vec<ServerStatus> {
+        let mut servers = self.servers.write().unwrap();
+        servers.retain(|_, (_, seen)| seen.elapsed() < self.expiry);
+        let mut online: Vec<ServerStatus> = servers.values().map(|(status, _)| status.clone()).collect();
+        online.sort_by(|a, b| a.name.cmp(&b.name));
+        online
+    }
+}
+
+// Fills the placeholders of a presence or channel name format.
+// `{track}` is only known when the format describes a single server.
+pub fn format_summary(format: &str, servers: &[ServerStatus]) -> String {
+    let players: usize = servers.iter().map(|server| server.players.len()).sum();
+    let capacity: u32 = servers.iter().map(|server| server.capacity).sum();
+    let track = match servers {
+        [server] => server.track.as_str(),
+        _ => "",
+    };
+    format
+        .replace("{players}", &players.to_string())
+        .replace("{capacity}", &capacity.to_string())
+        .replace("{servers}", &servers.len().to_string())
+        .replace("{track}", track)
+}
+
+// Reads the heartbeats stored by a separate API process (--bot-only) and removes the expired ones, the expiry is
+// read every time so config reloads apply
+pub async fn sync_loop(database: Arc<Database>, servers: Arc<ServerStatuses>, config: Arc<crate::config::SharedConfig>, mut shutdown: Shutdown) {
+    loop {
+        let expiry = config.get().api.heartbeat_expiry;
+        match database.prune_heartbeats(expiry).await {
+            Ok(0) => {},
+            Ok(pruned) => log::debug!("Pruned {} expired server heartbeats", pruned),
+            Err(e) => log::error!("Failed to prune server heartbeats: {}", e),
+        }
+        match database.recent_heartbeats(expiry).await {
+            Ok(heartbeats) => {
+                for (status, age) in heartbeats {
+                    servers.restore(status, age);
+                }
+            },
+            Err(e) => log::error!("Failed to read server heartbeats: {}", e),
+        }
+        tokio::select! {
+            _ = sleep(Duration::from_secs(10)) => {},
+            _ = shutdown.wait() => break,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use serde::Deserialize;
+
+use crate::config::Issues;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    // Failed deliveries are moved to the dead letter log after this many attempts
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    // Seconds before the first retry, doubled for every further one
+    #[serde(default = "default_backoff")]
+    pub backoff: u64,
+    // Seconds a target has to answer
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_backoff() -> u64 {
+    10
+}
+
+fn default_timeout() -> u64 {
+    10
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            max_attempts: default_max_attempts(),
+            backoff: default_backoff(),
+            timeout: default_timeout(),
+        }
+    }
+}
+
+impl WebhookConfig {
+    pub fn validate(&self, issues: &mut Issues) {
+        issues.check(self.max_attempts > 0, "webhooks.max_attempts", "must be at least 1");
+        issues.check(self.backoff > 0, "webhooks.backoff", "must be at least 1 second");
+        issues.check(self.timeout > 0, "webhooks.timeout", "must be at least 1 second");
+    }
+}
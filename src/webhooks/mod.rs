@@ -0,0 +1,204 @@
+pub mod config;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tokio::time::sleep;
+
+use crate::bus::{BotEvent, EventBus};
+use crate::config::SharedConfig;
+use crate::database::webhooks::Webhook;
+use crate::database::Database;
+use crate::metrics::WEBHOOK_DELIVERIES;
+use crate::shutdown::Shutdown;
+
+// Event names webhooks can subscribe to, `record` is a highscore which beat the track record
+pub const EVENTS: &[&str] = &["highscore", "record", "leaderboard", "link", "server_heartbeat", "ban", "unban"];
+
+// Subscriptions changed by another process are picked up after this long
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+// Retries back off exponentially up to this factor of `webhooks.backoff`
+const MAX_BACKOFF_FACTOR: u64 = 64;
+
+fn event_names(event: &BotEvent) -> Vec<&'static str> {
+    let mut names = vec![event.name()];
+    if let BotEvent::Highscore { server_record: true, .. } = event {
+        names.push("record");
+    }
+    names
+}
+
+// Hex encoded HMAC-SHA256 of `<timestamp>.<body>`, sent as `X-Swimbot-Signature: sha256=<signature>`
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn generate_secret() -> String {
+    format!("whsec_{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+fn payload(event: &str, data: Value) -> Value {
+    json!({
+        "id": uuid::Uuid::new_v4().to_string(),
+        "event": event,
+        "created_at": humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        "data": data,
+    })
+}
+
+enum Attempt {
+    Delivered,
+    // Network errors, timeouts, 429 and 5xx
+    Retry(String),
+    // Any other answer, retrying wouldn't change it
+    Failed(String),
+}
+
+// Posts bot events to the subscribed webhooks. Every delivery runs in its own task so a slow
+// target doesn't hold up the others, deliveries which fail every attempt end up in the dead letter log.
+#[derive(Debug)]
+pub struct Dispatcher {
+    client: reqwest::Client,
+    database: Arc<Database>,
+    config: Arc<SharedConfig>,
+    webhooks: RwLock<Option<(Instant, Arc<Vec<Webhook>>)>>,
+}
+
+impl Dispatcher {
+    pub fn new(database: Arc<Database>, config: Arc<SharedConfig>) -> Dispatcher {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("swimbot/", env!("CARGO_PKG_VERSION")))
+            // A target must not send the signed payload on to somewhere else
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to create the webhook client");
+        Dispatcher {
+            client,
+            database,
+            config,
+            webhooks: RwLock::new(None),
+        }
+    }
+
+    // Forgets the cached subscriptions, called after they were changed
+    pub async fn invalidate(&self) {
+        *self.webhooks.write().await = None;
+    }
+
+    async fn webhooks(&self) -> Result<Arc<Vec<Webhook>>, tokio_postgres::Error> {
+        if let Some((loaded, webhooks)) = &*self.webhooks.read().await {
+            if loaded.elapsed() < CACHE_TTL {
+                return Ok(webhooks.clone())
+            }
+        }
+        let webhooks = Arc::new(self.database.webhooks().await?);
+        *self.webhooks.write().await = Some((Instant::now(), webhooks.clone()));
+        Ok(webhooks)
+    }
+
+    pub async fn run(self: Arc<Self>, bus: Arc<EventBus>, mut shutdown: Shutdown) {
+        let mut events = bus.subscribe();
+        loop {
+            let event = tokio::select! {
+                received = events.recv() => match received {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("Webhooks missed {} events", skipped);
+                        continue
+                    },
+                    Err(RecvError::Closed) => break,
+                },
+                _ = shutdown.wait() => break,
+            };
+
+            let webhooks = match self.webhooks().await {
+                Ok(webhooks) => webhooks,
+                Err(e) => {
+                    log::error!("Failed to fetch webhooks: {}", e);
+                    continue
+                },
+            };
+            let names = event_names(&event);
+            let subscribed = webhooks.iter().filter(|webhook| webhook.events.iter().any(|name| names.contains(&name.as_str())));
+            for webhook in subscribed {
+                let payload = payload(event.name(), json!(event));
+                tokio::spawn(self.clone().deliver(webhook.clone(), event.name(), payload, shutdown.clone()));
+            }
+        }
+        log::debug!("Stopped delivering webhooks");
+    }
+
+    // Sends a test event once, without retries or dead letter
+    pub async fn test(&self, webhook: &Webhook) -> Result<(), String> {
+        let payload = payload("test", json!({ "message": "Test delivery from swim> bot" }));
+        match self.attempt(webhook, "test", payload.to_string().as_bytes()).await {
+            Attempt::Delivered => Ok(()),
+            Attempt::Retry(error) | Attempt::Failed(error) => Err(error),
+        }
+    }
+
+    async fn deliver(self: Arc<Self>, webhook: Webhook, event: &'static str, payload: Value, mut shutdown: Shutdown) {
+        let config = self.config.get().webhooks.clone();
+        let body = payload.to_string();
+        let mut attempts: u32 = 0;
+        let error = loop {
+            attempts += 1;
+            match self.attempt(&webhook, event, body.as_bytes()).await {
+                Attempt::Delivered => {
+                    WEBHOOK_DELIVERIES.with_label_values(&["delivered"]).inc();
+                    return
+                },
+                Attempt::Failed(error) => break error,
+                Attempt::Retry(error) if attempts >= config.max_attempts => break error,
+                Attempt::Retry(error) => {
+                    WEBHOOK_DELIVERIES.with_label_values(&["retried"]).inc();
+                    let factor = 2u64.saturating_pow(attempts - 1).min(MAX_BACKOFF_FACTOR);
+                    let backoff = Duration::from_secs(config.backoff * factor);
+                    log::warn!("Delivering {} to webhook {} failed: {}, retrying in {}s", event, webhook.id, error, backoff.as_secs());
+                    tokio::select! {
+                        _ = sleep(backoff) => {},
+                        // Recorded right away, the shutdown won't wait for the backoff
+                        _ = shutdown.wait() => break format!("{} (not retried because of a shutdown)", error),
+                    }
+                },
+            }
+        };
+
+        WEBHOOK_DELIVERIES.with_label_values(&["failed"]).inc();
+        log::error!("Giving up on delivering {} to webhook {} after {} attempt(s): {}", event, webhook.id, attempts, error);
+        if let Err(e) = self.database.insert_dead_letter(webhook.id, event, &payload, attempts as i32, &error).await {
+            log::error!("Failed to store the dead letter of webhook {}: {}", webhook.id, e);
+        }
+    }
+
+    async fn attempt(&self, webhook: &Webhook, event: &str, body: &[u8]) -> Attempt {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let response = self.client.post(&webhook.url)
+            .timeout(Duration::from_secs(self.config.get().webhooks.timeout))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("x-swimbot-event", event)
+            .header("x-swimbot-timestamp", timestamp.to_string())
+            .header("x-swimbot-signature", format!("sha256={}", sign(&webhook.secret, timestamp, body)))
+            .body(body.to_vec())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => Attempt::Delivered,
+            Ok(response) if response.status().is_server_error() || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                Attempt::Retry(format!("HTTP {}", response.status()))
+            },
+            Ok(response) => Attempt::Failed(format!("HTTP {}", response.status())),
+            Err(e) => Attempt::Retry(e.to_string()),
+        }
+    }
+}